@@ -1,28 +1,71 @@
-use reqwest::{header, Client, Method, StatusCode};
-use serde::de::DeserializeOwned;
+use std::{sync::Mutex, time::Duration};
+
+use chrono::{DateTime, TimeZone, Utc};
+use reqwest::{header, header::HeaderMap, Client, Method, Response, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{fs, io::AsyncWriteExt, time::timeout};
+use tracing::{info, warn};
+
+const CONNECT_TIMEOUT_SECS: u64 = 30;
+const REQUEST_TIMEOUT_SECS: u64 = 60;
+//Downloads may take long as a whole, but must never stall for longer than this
+const DOWNLOAD_IDLE_TIMEOUT_SECS: u64 = 60;
+
+//Rate limit is shared by every client of the process, so the scheduler can wait for it
+static LAST_RATE_LIMIT: Mutex<Option<RateLimitState>> = Mutex::new(None);
 
 #[derive(Debug)]
 pub struct AppClient {
     base_url: String,
     client: Client,
+    bearer_token: Option<String>,
 }
 
 impl AppClient {
     pub fn new(base_url: String) -> Self {
         AppClient {
             base_url,
-            client: Client::new(),
+            client: Client::builder()
+                .connect_timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS))
+                .build()
+                .unwrap_or_else(|_| Client::new()),
+            bearer_token: None,
         }
     }
 
-    async fn send_request<T: DeserializeOwned>(
+    pub fn with_bearer_token(mut self, bearer_token: Option<String>) -> Self {
+        self.bearer_token = bearer_token;
+        self
+    }
+
+    pub fn last_rate_limit() -> Option<RateLimitState> {
+        LAST_RATE_LIMIT.lock().ok().and_then(|state| state.clone())
+    }
+
+    async fn execute_request(
         &self,
         method: Method,
         url: &str,
         body: Option<String>,
-    ) -> Result<RestResponse<T>, String> {
-        let request_builder = self.client.request(method, self.full_url(url));
+        validators: Option<&CacheValidators>,
+    ) -> Result<Response, String> {
+        let request_builder = self
+            .client
+            .request(method, self.full_url(url))
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS));
         let request_builder = request_builder.header(header::USER_AGENT, "nym-updater/0.1.0");
+        let request_builder = match &self.bearer_token {
+            Some(token) => request_builder.bearer_auth(token),
+            None => request_builder,
+        };
+        let request_builder = match validators.and_then(|v| v.etag.as_ref()) {
+            Some(etag) => request_builder.header(header::IF_NONE_MATCH, etag),
+            None => request_builder,
+        };
+        let request_builder = match validators.and_then(|v| v.last_modified.as_ref()) {
+            Some(last_modified) => request_builder.header(header::IF_MODIFIED_SINCE, last_modified),
+            None => request_builder,
+        };
         let request_builder = match body {
             Some(b) => request_builder.body(b),
             None => request_builder,
@@ -39,6 +82,40 @@ impl AppClient {
             )
         })?;
 
+        if let Some(rate_limit) = RateLimitState::from_headers(response.headers()) {
+            if rate_limit.remaining == 0 {
+                warn!("Rate limit exhausted until {}", rate_limit.reset_at);
+            }
+            if let Ok(mut state) = LAST_RATE_LIMIT.lock() {
+                *state = Some(rate_limit);
+            }
+        }
+
+        Ok(response)
+    }
+
+    async fn send_request<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        url: &str,
+        body: Option<String>,
+    ) -> Result<(RestResponse<T>, HeaderMap), String> {
+        let response = self.execute_request(method, url, body, None).await?;
+        self.parse_response(response).await
+    }
+
+    async fn parse_response<T: DeserializeOwned>(
+        &self,
+        response: Response,
+    ) -> Result<(RestResponse<T>, HeaderMap), String> {
+        let headers = response.headers().clone();
+
+        let is_rate_limited = matches!(
+            response.status(),
+            StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS
+        ) && RateLimitState::from_headers(&headers)
+            .is_some_and(|limit| limit.remaining == 0);
+
         let result = match response.status() {
             StatusCode::OK => {
                 let result = response
@@ -49,6 +126,12 @@ impl AppClient {
 
                 RestResponse::Success(result)
             }
+            _ if is_rate_limited => {
+                let reset_at = RateLimitState::from_headers(&headers)
+                    .map(|limit| limit.reset_at)
+                    .unwrap_or_else(Utc::now);
+                RestResponse::RateLimited { reset_at }
+            }
             _ => {
                 let res_text = response.text().await.map_err(|e| {
                     format!(
@@ -59,20 +142,366 @@ impl AppClient {
                 RestResponse::Error { message: res_text }
             }
         };
-        Ok(result)
+        Ok((result, headers))
     }
 
     fn full_url(&self, url: &str) -> String {
+        //Pagination links are already absolute
+        if url.starts_with("http://") || url.starts_with("https://") {
+            return url.to_string();
+        }
         format!("{}{}", self.base_url, url)
     }
 
     pub async fn get<T: DeserializeOwned>(&self, url: &str) -> Result<RestResponse<T>, String> {
-        let res = self.send_request(Method::GET, url, None).await?;
+        let (res, _) = self.send_request(Method::GET, url, None).await?;
         Ok(res)
     }
+
+    //Same as get, also returning the `rel="next"` url of the Link header and the cache validators.
+    //With validators the request is conditional, and None is returned when the content is not modified
+    pub async fn get_page<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        validators: Option<&CacheValidators>,
+    ) -> Result<Option<RestPage<T>>, String> {
+        let response = self
+            .execute_request(Method::GET, url, None, validators)
+            .await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+
+        let (response, headers) = self.parse_response(response).await?;
+        let next_url = headers
+            .get(header::LINK)
+            .and_then(|link| link.to_str().ok())
+            .and_then(next_link_url);
+        Ok(Some(RestPage {
+            response,
+            next_url,
+            validators: CacheValidators::from_headers(&headers),
+        }))
+    }
+
+    pub async fn get_text(&self, url: &str) -> Result<RestResponse<String>, String> {
+        self.get_text_with_timeout(url, Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .await
+    }
+
+    //request_timeout bounds the whole request, from connecting until the body is read
+    pub async fn get_text_with_timeout(
+        &self,
+        url: &str,
+        request_timeout: Duration,
+    ) -> Result<RestResponse<String>, String> {
+        let response = self
+            .client
+            .get(self.full_url(url))
+            .header(header::USER_AGENT, "nym-updater/0.1.0")
+            .timeout(request_timeout)
+            .send()
+            .await
+            .map_err(|e| {
+                format!(
+                    "Failed to execute request to url: {} with error: {}",
+                    url, e
+                )
+            })?;
+
+        let is_success = response.status() == StatusCode::OK;
+        let res_text = response
+            .text()
+            .await
+            .map_err(|e| format!("Failed to parse response body text: {}", e))?;
+
+        if is_success {
+            Ok(RestResponse::Success(res_text))
+        } else {
+            Ok(RestResponse::Error { message: res_text })
+        }
+    }
+
+    //Streams url into `<dest>.part`, resuming an earlier partial download when possible,
+    //and only moves it to dest once the full content is received
+    pub async fn download_to_file(
+        &self,
+        url: &str,
+        dest: &str,
+        expected_size: Option<u64>,
+    ) -> Result<(), String> {
+        let part_path = format!("{}.part", dest);
+        let mut downloaded = match fs::metadata(&part_path).await {
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        };
+
+        if let Some(size) = expected_size {
+            if downloaded > size {
+                info!("Discarding oversized partial download {}", part_path);
+                downloaded = 0;
+            }
+        }
+
+        let mut request_builder = self
+            .client
+            .get(self.full_url(url))
+            .header(header::USER_AGENT, "nym-updater/0.1.0");
+        //reqwest drops the token when a download redirects to another host
+        if let Some(token) = &self.bearer_token {
+            request_builder = request_builder.bearer_auth(token);
+        }
+        if downloaded > 0 {
+            info!("Resuming download of {} from byte {}", url, downloaded);
+            request_builder =
+                request_builder.header(header::RANGE, format!("bytes={}-", downloaded));
+        }
+
+        let idle_timeout = Duration::from_secs(DOWNLOAD_IDLE_TIMEOUT_SECS);
+        let mut response = timeout(idle_timeout, request_builder.send())
+            .await
+            .map_err(|_| format!("Request to url: {} timed out", url))?
+            .map_err(|e| {
+                format!(
+                    "Failed to execute request to url: {} with error: {}",
+                    url, e
+                )
+            })?;
+
+        let is_already_complete = response.status() == StatusCode::RANGE_NOT_SATISFIABLE
+            && expected_size == Some(downloaded);
+
+        if !is_already_complete {
+            let append = match response.status() {
+                StatusCode::PARTIAL_CONTENT => true,
+                StatusCode::OK => {
+                    //Server ignored the range header, so the download starts over
+                    downloaded = 0;
+                    false
+                }
+                status => {
+                    if status == StatusCode::RANGE_NOT_SATISFIABLE {
+                        //Partial file does not match the remote file anymore
+                        let _ = fs::remove_file(&part_path).await;
+                    }
+                    return Err(format!("Failed to download {} with status {}", url, status));
+                }
+            };
+
+            let mut file = fs::OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(append)
+                .truncate(!append)
+                .open(&part_path)
+                .await
+                .map_err(|e| format!("Error while opening {} with {} error", part_path, e))?;
+
+            let total_size =
+                expected_size.or_else(|| response.content_length().map(|len| len + downloaded));
+            let mut last_logged_percent = 0;
+
+            //Partial file is kept, so a stalled download resumes on the next attempt
+            while let Some(chunk) = timeout(idle_timeout, response.chunk())
+                .await
+                .map_err(|_| {
+                    format!(
+                        "Download of {} stalled for {} seconds",
+                        url, DOWNLOAD_IDLE_TIMEOUT_SECS
+                    )
+                })?
+                .map_err(|e| format!("Error while downloading {} with {} error", url, e))?
+            {
+                file.write_all(&chunk)
+                    .await
+                    .map_err(|e| format!("Error while writing {} with {} error", part_path, e))?;
+                downloaded += chunk.len() as u64;
+
+                if let Some(total) = total_size.filter(|total| *total > 0) {
+                    let percent = downloaded * 100 / total;
+                    if percent >= last_logged_percent + 10 {
+                        last_logged_percent = percent - percent % 10;
+                        info!(
+                            "Downloaded {}% ({}/{} bytes) of {}",
+                            percent, downloaded, total, url
+                        );
+                    }
+                }
+            }
+
+            file.flush()
+                .await
+                .map_err(|e| format!("Error while writing {} with {} error", part_path, e))?;
+        }
+
+        if let Some(size) = expected_size {
+            if downloaded != size {
+                //Keep the partial file only when it can still be resumed
+                if downloaded > size {
+                    let _ = fs::remove_file(&part_path).await;
+                }
+                return Err(format!(
+                    "Downloaded size of {} is {} bytes, expected {} bytes",
+                    url, downloaded, size
+                ));
+            }
+        }
+
+        fs::rename(&part_path, dest).await.map_err(|e| {
+            format!(
+                "Error while moving {} to {} with {} error",
+                part_path, dest, e
+            )
+        })?;
+
+        info!("Downloaded {} to {} ({} bytes)", url, dest, downloaded);
+        Ok(())
+    }
 }
 
 pub enum RestResponse<T> {
     Success(T),
     Error { message: String },
+    RateLimited { reset_at: DateTime<Utc> },
+}
+
+pub struct RestPage<T> {
+    pub response: RestResponse<T>,
+    pub next_url: Option<String>,
+    pub validators: CacheValidators,
+}
+
+//ETag and Last-Modified of a response, sent back to make the next request conditional
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheValidators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl CacheValidators {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let header_string = |name: header::HeaderName| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+
+        Self {
+            etag: header_string(header::ETAG),
+            last_modified: header_string(header::LAST_MODIFIED),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitState {
+    pub remaining: u64,
+    pub reset_at: DateTime<Utc>,
+}
+
+impl RateLimitState {
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header_u64 = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u64>().ok())
+        };
+
+        let remaining = header_u64("x-ratelimit-remaining")?;
+        let reset_at = Utc
+            .timestamp_opt(header_u64("x-ratelimit-reset")? as i64, 0)
+            .single()?;
+
+        Some(Self {
+            remaining,
+            reset_at,
+        })
+    }
+}
+
+//Parses `<url>; rel="next", <url>; rel="last"` style Link headers
+fn next_link_url(link_header: &str) -> Option<String> {
+    link_header.split(',').find_map(|link| {
+        let (url, params) = link.split_once(';')?;
+        params
+            .split(';')
+            .any(|param| param.trim() == r#"rel="next""#)
+            .then(|| {
+                url.trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_string()
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_next_link_among_others() {
+        let link_header = r#"<https://api.github.com/repositories/1/releases?per_page=100&page=2>; rel="next", <https://api.github.com/repositories/1/releases?per_page=100&page=5>; rel="last""#;
+        assert_eq!(
+            next_link_url(link_header).as_deref(),
+            Some("https://api.github.com/repositories/1/releases?per_page=100&page=2")
+        );
+    }
+
+    #[test]
+    fn finds_next_link_in_any_position() {
+        let link_header = r#"<https://example.com/releases?page=1>; rel="prev", <https://example.com/releases?page=3>; rel="next""#;
+        assert_eq!(
+            next_link_url(link_header).as_deref(),
+            Some("https://example.com/releases?page=3")
+        );
+    }
+
+    #[test]
+    fn returns_none_on_last_page() {
+        let link_header = r#"<https://example.com/releases?page=1>; rel="prev", <https://example.com/releases?page=1>; rel="first""#;
+        assert_eq!(next_link_url(link_header), None);
+        assert_eq!(next_link_url(""), None);
+        assert_eq!(next_link_url("<https://example.com/releases?page=2>"), None);
+    }
+
+    #[tokio::test]
+    async fn download_sends_bearer_token() {
+        use tokio::{io::AsyncReadExt, net::TcpListener, sync::oneshot};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/nym-mixnode", listener.local_addr().unwrap());
+        let (request_sender, request_receiver) = oneshot::channel();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![];
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..read]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\nConnection: close\r\n\r\nnode")
+                .await
+                .unwrap();
+            let _ = request_sender.send(String::from_utf8_lossy(&request).to_lowercase());
+        });
+
+        let dest =
+            std::env::temp_dir().join(format!("nym-updater-download-{}", std::process::id()));
+        let dest = dest.to_str().unwrap();
+        let client = AppClient::new(String::new()).with_bearer_token(Some("secret".to_string()));
+        client.download_to_file(&url, dest, Some(4)).await.unwrap();
+
+        assert_eq!(std::fs::read_to_string(dest).unwrap(), "node");
+        assert!(request_receiver
+            .await
+            .unwrap()
+            .contains("authorization: bearer secret"));
+        let _ = std::fs::remove_file(dest);
+    }
 }
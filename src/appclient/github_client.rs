@@ -1,4 +1,12 @@
-use super::{base_client::AppClient, GithubApiUrl, GithubReleasesResponse, RestResponse};
+use tracing::info;
+
+use super::{
+    base_client::AppClient, CacheValidators, GithubApiUrl, GithubRelease, GithubReleasesResponse,
+    RestResponse,
+};
+
+const RELEASES_PER_PAGE: u32 = 100;
+const MAX_RELEASE_PAGES: usize = 5;
 
 #[derive(Debug)]
 pub struct GithubClient {
@@ -13,6 +21,7 @@ impl GithubClient {
             base_url,
             owner,
             repo,
+            token,
         } = params;
 
         let base_url = base_url.unwrap_or("https://api.github.com".to_string());
@@ -20,15 +29,73 @@ impl GithubClient {
         GithubClient {
             owner,
             repo,
-            client: AppClient::new(base_url),
+            client: AppClient::new(base_url).with_bearer_token(token),
         }
     }
 
+    //Follows Link pagination up to MAX_RELEASE_PAGES, a failing later page keeps the releases read so far.
+    //Only the first page is conditional on validators, None means the release list is not modified
     pub async fn latest_repo_release_list(
         &self,
-    ) -> Result<RestResponse<GithubReleasesResponse>, String> {
-        let url = GithubApiUrl::repo_releases(self.owner.clone(), self.repo.clone());
-        let res = self.client.get::<GithubReleasesResponse>(&url).await?;
+        validators: Option<&CacheValidators>,
+    ) -> Result<Option<(RestResponse<GithubReleasesResponse>, CacheValidators)>, String> {
+        let mut url = Some(format!(
+            "{}?per_page={}",
+            GithubApiUrl::repo_releases(self.owner.clone(), self.repo.clone()),
+            RELEASES_PER_PAGE
+        ));
+        let mut releases: GithubReleasesResponse = vec![];
+        let mut first_page_validators = CacheValidators::default();
+
+        for page in 1..=MAX_RELEASE_PAGES {
+            let Some(page_url) = url.take() else {
+                break;
+            };
+
+            let page_validators = if page == 1 { validators } else { None };
+            let Some(res) = self
+                .client
+                .get_page::<GithubReleasesResponse>(&page_url, page_validators)
+                .await?
+            else {
+                return Ok(None);
+            };
+            if page == 1 {
+                first_page_validators = res.validators;
+            }
+
+            match res.response {
+                RestResponse::Success(page_releases) => releases.extend(page_releases),
+                other if page == 1 => return Ok(Some((other, first_page_validators))),
+                _ => {
+                    info!(
+                        "Failed to get release page {}, using first pages only",
+                        page
+                    );
+                    //Incomplete list must not be confirmed by a later 304 on the first page
+                    first_page_validators = CacheValidators::default();
+                    break;
+                }
+            }
+            url = res.next_url;
+        }
+
+        Ok(Some((
+            RestResponse::Success(releases),
+            first_page_validators,
+        )))
+    }
+
+    pub async fn repo_release_by_tag(
+        &self,
+        tag: &str,
+    ) -> Result<RestResponse<GithubRelease>, String> {
+        let url = GithubApiUrl::repo_release_by_tag(
+            self.owner.clone(),
+            self.repo.clone(),
+            tag.to_string(),
+        );
+        let res = self.client.get::<GithubRelease>(&url).await?;
         Ok(res)
     }
 }
@@ -37,4 +104,5 @@ pub struct GithubClConstructorParams {
     pub owner: String,
     pub repo: String,
     pub base_url: Option<String>,
+    pub token: Option<String>,
 }
@@ -18,7 +18,8 @@ pub struct GithubRelease {
     pub draft: bool,
     pub prerelease: bool,
     pub created_at: String,
-    pub published_at: String,
+    //GitHub sends null for drafts, which are only listed to tokens with push access
+    pub published_at: Option<String>,
     pub assets: Vec<Asset>,
     pub tarball_url: String,
     pub zipball_url: String,
@@ -63,6 +64,9 @@ pub struct Asset {
     pub created_at: String,
     pub updated_at: String,
     pub browser_download_url: String,
+    //Only present on assets uploaded after GitHub started publishing digests, e.g. "sha256:<hex>"
+    #[serde(default)]
+    pub digest: Option<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
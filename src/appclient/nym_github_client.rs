@@ -1,82 +1,372 @@
-use std::str::FromStr;
+use std::{str::FromStr, time::Duration};
 
 use chrono::{DateTime, Utc};
+use regex::Regex;
+use tracing::{error, info, warn};
 
-use crate::constants::NymReleaseAssets;
+use crate::{
+    constants::NymReleaseAssets,
+    util::{
+        NymChecksumUtil, NymReleaseCache, NymReleaseCacheUtil, NymReleaseChannel, NymUpstreamConfig,
+    },
+};
 
 use super::{
-    GithubClConstructorParams, GithubClient, GithubRelease, GithubReleasesResponse, RestResponse,
+    AppClient, Asset, GithubClConstructorParams, GithubClient, GithubRelease,
+    GithubReleasesResponse, RestResponse,
 };
 
+const CHECKSUM_FILE_NAMES: [&str; 3] = ["SHA256SUMS", "sha256sums.txt", "checksums.txt"];
+const NYM_HASHES_FILE_NAME: &str = "hashes.json";
+
 #[derive(Debug)]
 pub struct NymGithubClient {
-    owner: String,
-    repo: String,
     client: GithubClient,
+    tag_regex: Regex,
+    release_cache_path: String,
+    //Release asset urls are absolute, so this client has no base url. It sends the same token, as
+    //assets of a private fork or an internal mirror can not be downloaded without it
+    download_client: AppClient,
 }
 
-impl NymGithubClient {
-    pub fn new() -> Self {
-        let repo = "nym".to_string();
-        let owner = "nymtech".to_string();
+//Error while fetching a release asset. Transport errors say nothing about the release, every other
+//error means the release itself can not be used, e.g. a missing asset or checksum
+#[derive(Debug)]
+pub enum NymReleaseError {
+    Transport(String),
+    Release(String),
+}
 
+impl From<NymReleaseError> for String {
+    fn from(value: NymReleaseError) -> Self {
+        match value {
+            NymReleaseError::Transport(e) | NymReleaseError::Release(e) => e,
+        }
+    }
+}
+
+impl NymGithubClient {
+    pub fn new(upstream: &NymUpstreamConfig) -> Result<Self, String> {
+        let token = upstream.token()?;
         let nym_params = GithubClConstructorParams {
-            owner: owner.clone(),
-            repo: repo.clone(),
-            base_url: None,
+            owner: upstream.owner.clone(),
+            repo: upstream.repo.clone(),
+            base_url: upstream.api_base_url.clone(),
+            token: token.clone(),
         };
 
-        NymGithubClient {
-            owner,
-            repo,
+        Ok(NymGithubClient {
             client: GithubClient::new(nym_params),
-        }
+            tag_regex: upstream.tag_regex()?,
+            release_cache_path: NymReleaseCacheUtil::cache_path(upstream),
+            download_client: AppClient::new(String::new()).with_bearer_token(token),
+        })
     }
 
+    //Release list is cached on disk, a 304 reuses the cache and a failing request falls back to it
     async fn latest_nym_release_list(&self) -> Result<GithubReleasesResponse, String> {
-        match self.client.latest_repo_release_list().await? {
-            RestResponse::Success(res) => Ok(res),
-            RestResponse::Error { message } => {
-                dbg!(&message);
-                Err(format!(
-                    "{},{}",
-                    message, "Failed to get latest nym release list"
-                ))
+        let cache = NymReleaseCacheUtil::read_release_cache(&self.release_cache_path);
+        let validators = cache.as_ref().map(|cache| &cache.validators);
+
+        let fetch_err = match self.client.latest_repo_release_list(validators).await {
+            Ok(Some((RestResponse::Success(res), validators))) => {
+                self.write_release_cache(NymReleaseCache {
+                    validators,
+                    checked_at: Utc::now(),
+                    releases: res.clone(),
+                });
+                return Ok(res);
             }
+            Ok(None) => match cache {
+                Some(mut cache) => {
+                    info!("Release list not modified, using cached release list");
+                    cache.checked_at = Utc::now();
+                    self.write_release_cache(cache.clone());
+                    return Ok(cache.releases);
+                }
+                None => "Release list not modified but no cache exists".to_string(),
+            },
+            Ok(Some((RestResponse::Error { message }, _))) => {
+                error!("Failed to get latest nym release list: {}", message);
+                format!("{},{}", message, "Failed to get latest nym release list")
+            }
+            Ok(Some((RestResponse::RateLimited { reset_at }, _))) => format!(
+                "GitHub rate limit exceeded until {}, Failed to get latest nym release list",
+                reset_at
+            ),
+            Err(e) => e,
+        };
+
+        match self.stale_release_cache(&fetch_err) {
+            Some(cache) => Ok(cache.releases),
+            None => Err(fetch_err),
         }
     }
 
-    pub async fn latest_nym_release(&self) -> Result<GithubRelease, String> {
-        let nym_binaries_tag = "nym-binaries";
+    fn write_release_cache(&self, cache: NymReleaseCache) {
+        if let Err(e) = NymReleaseCacheUtil::write_release_cache(&self.release_cache_path, &cache) {
+            error!("Failed to write release cache: {}", e);
+        }
+    }
+
+    fn stale_release_cache(&self, fetch_err: &str) -> Option<NymReleaseCache> {
+        let cache = NymReleaseCacheUtil::read_release_cache(&self.release_cache_path)?;
+        warn!(
+            "GitHub is unreachable ({}), using cached release list last checked at {} ({} hours old)",
+            fetch_err,
+            cache.checked_at,
+            cache.age_hours()
+        );
+        Some(cache)
+    }
+
+    pub async fn latest_nym_release(
+        &self,
+        channel: &NymReleaseChannel,
+    ) -> Result<GithubRelease, String> {
         let releases_list = self.latest_nym_release_list().await?;
         let latest_nym_binaries_release = releases_list
             .into_iter()
-            .filter(|release| release.tag_name.contains(nym_binaries_tag))
-            .max_by_key(|release| {
-                DateTime::<Utc>::from_str(&release.published_at).unwrap_or_else(|_| {
-                    println!("Failed to parse release date: {}", &release.published_at);
-                    Utc::now()
-                })
-            });
+            .filter(|release| self.tag_regex.is_match(&release.tag_name))
+            .filter(|release| channel.accepts(release))
+            //An undated release can not be ordered, so it is never taken as the latest
+            .filter_map(|release| Some((Self::release_published_at(&release)?, release)))
+            .max_by_key(|(published_at, _)| *published_at);
 
         match latest_nym_binaries_release {
-            Some(release) => Ok(release),
+            Some((_, release)) => Ok(release),
             None => Err(format!(
-                "Failed to find latest nym release with tag: {}",
-                nym_binaries_tag
+                "Failed to find latest nym release with tag: {} on {} channel",
+                self.tag_regex,
+                channel.as_str()
             )),
         }
     }
 
-    pub fn latest_release_download_url(&self, asset: &NymReleaseAssets) -> Result<String, String> {
-        let download_base_url = format!(
-            "https://github.com/{}/{}/releases/latest/download/",
-            &self.owner, &self.repo
+    pub fn release_published_at(release: &GithubRelease) -> Option<DateTime<Utc>> {
+        let Some(published_at) = &release.published_at else {
+            warn!(
+                "Release {} has no publish date, skipping it",
+                release.tag_name
+            );
+            return None;
+        };
+
+        DateTime::<Utc>::from_str(published_at)
+            .map_err(|e| {
+                warn!(
+                    "Failed to parse release {} date {} with {} error, skipping it",
+                    release.tag_name, published_at, e
+                )
+            })
+            .ok()
+    }
+
+    pub async fn nym_release_by_tag(
+        &self,
+        tag: &str,
+        channel: &NymReleaseChannel,
+    ) -> Result<GithubRelease, String> {
+        let release = match self.client.repo_release_by_tag(tag).await {
+            Ok(RestResponse::Success(release)) => Ok(release),
+            Ok(RestResponse::Error { message }) => Err(format!(
+                "{},Failed to get nym release with tag: {}",
+                message, tag
+            )),
+            Ok(RestResponse::RateLimited { reset_at }) => Err(format!(
+                "GitHub rate limit exceeded until {}, Failed to get nym release with tag: {}",
+                reset_at, tag
+            )),
+            Err(e) => Err(e),
+        };
+
+        //Pinned releases are looked up in the cached release list when GitHub is unreachable
+        let release = release.or_else(|fetch_err| {
+            self.stale_release_cache(&fetch_err)
+                .and_then(|cache| {
+                    cache
+                        .releases
+                        .into_iter()
+                        .find(|release| release.tag_name == tag)
+                })
+                .ok_or(fetch_err)
+        })?;
+
+        if !channel.accepts(&release) {
+            return Err(format!(
+                "Release {} is a draft or prerelease and can not be installed on {} channel",
+                tag,
+                channel.as_str()
+            ));
+        }
+
+        Ok(release)
+    }
+
+    pub async fn release_asset_checksum(
+        &self,
+        release: &GithubRelease,
+        asset: &NymReleaseAssets,
+    ) -> Result<String, NymReleaseError> {
+        let asset_name = asset.name();
+        let release_asset =
+            Self::release_asset(release, asset_name).map_err(NymReleaseError::Release)?;
+
+        if let Some(checksum) = release_asset
+            .digest
+            .as_deref()
+            .and_then(|digest| digest.strip_prefix("sha256:"))
+        {
+            info!("Using GitHub digest as {} checksum", asset_name);
+            return Ok(checksum.to_lowercase());
+        }
+
+        //A checksum file that could not be downloaded may still hold the checksum
+        let mut download_err = None;
+        let sha256_file_name = format!("{}.sha256", asset_name);
+        for checksum_asset in &release.assets {
+            let checksum_asset_name = checksum_asset.name.as_str();
+            let is_asset_checksum_file = checksum_asset_name == sha256_file_name;
+            let is_checksums_file =
+                is_asset_checksum_file || CHECKSUM_FILE_NAMES.contains(&checksum_asset_name);
+            let is_hashes_file = checksum_asset_name == NYM_HASHES_FILE_NAME;
+            if !is_checksums_file && !is_hashes_file {
+                continue;
+            }
+
+            let content = match self
+                .download_client
+                .get_text(&checksum_asset.browser_download_url)
+                .await
+                .map_err(NymReleaseError::Transport)?
+            {
+                RestResponse::Success(content) => content,
+                RestResponse::Error { message } => {
+                    info!(
+                        "Failed to download checksum file {}: {}",
+                        checksum_asset_name, message
+                    );
+                    download_err = Some(message);
+                    continue;
+                }
+                RestResponse::RateLimited { reset_at } => {
+                    info!(
+                        "Failed to download checksum file {}, rate limited until {}",
+                        checksum_asset_name, reset_at
+                    );
+                    download_err = Some(format!("Rate limited until {}", reset_at));
+                    continue;
+                }
+            };
+
+            let checksum = if is_hashes_file {
+                NymChecksumUtil::find_in_hashes_json(&content, asset_name)
+            } else {
+                NymChecksumUtil::find_in_checksums_file(
+                    &content,
+                    asset_name,
+                    is_asset_checksum_file,
+                )
+            };
+
+            if let Some(checksum) = checksum {
+                info!(
+                    "Using {} from {} as {} checksum",
+                    checksum, checksum_asset_name, asset_name
+                );
+                return Ok(checksum);
+            }
+        }
+
+        let err = format!(
+            "Release {} has no published checksum for {}",
+            release.tag_name, asset_name
+        );
+        match download_err {
+            Some(download_err) => Err(NymReleaseError::Transport(format!(
+                "{}, {}",
+                download_err, err
+            ))),
+            None => Err(NymReleaseError::Release(err)),
+        }
+    }
+
+    pub async fn release_asset_signature(
+        &self,
+        release: &GithubRelease,
+        asset: &NymReleaseAssets,
+    ) -> Result<String, NymReleaseError> {
+        let signature_name = format!("{}.minisig", asset.name());
+        let signature_asset =
+            Self::release_asset(release, &signature_name).map_err(NymReleaseError::Release)?;
+
+        match self
+            .download_client
+            .get_text(&signature_asset.browser_download_url)
+            .await
+            .map_err(NymReleaseError::Transport)?
+        {
+            RestResponse::Success(content) => Ok(content),
+            RestResponse::Error { message } => Err(NymReleaseError::Transport(format!(
+                "{}, Failed to download signature {}",
+                message, signature_name
+            ))),
+            RestResponse::RateLimited { reset_at } => Err(NymReleaseError::Transport(format!(
+                "Rate limited until {}, Failed to download signature {}",
+                reset_at, signature_name
+            ))),
+        }
+    }
+
+    pub fn release_asset<'a>(release: &'a GithubRelease, name: &str) -> Result<&'a Asset, String> {
+        release
+            .assets
+            .iter()
+            .find(|release_asset| release_asset.name == name)
+            .ok_or_else(|| {
+                let available_assets = release
+                    .assets
+                    .iter()
+                    .map(|release_asset| release_asset.name.as_str())
+                    .collect::<Vec<_>>();
+                format!(
+                    "Release {} has no {} asset, available assets are {:?}",
+                    release.tag_name, name, available_assets
+                )
+            })
+    }
+
+    pub async fn download_release_asset(
+        &self,
+        release: &GithubRelease,
+        asset: &NymReleaseAssets,
+        dest: &str,
+    ) -> Result<(), NymReleaseError> {
+        //Tag, version and binary must all come from the same selected release
+        let release_asset =
+            Self::release_asset(release, asset.name()).map_err(NymReleaseError::Release)?;
+        let download_url = &release_asset.browser_download_url;
+        info!(
+            "Downloading {} of release {} from {}",
+            asset.name(),
+            release.tag_name,
+            download_url
         );
 
-        match asset {
-            NymReleaseAssets::MixNode => Ok(format!("{}{}", download_base_url, asset.name())),
-            NymReleaseAssets::Gateway => Err("Gateway is not supported yet".to_string()),
+        let expected_size = u64::try_from(release_asset.size).ok();
+        self.download_client
+            .download_to_file(download_url, dest, expected_size)
+            .await
+            .map_err(NymReleaseError::Transport)
+    }
+
+    //Time to wait before GitHub accepts requests again, if the rate limit is exhausted
+    pub fn rate_limit_wait() -> Option<Duration> {
+        let rate_limit = AppClient::last_rate_limit()?;
+        if rate_limit.remaining > 0 {
+            return None;
         }
+
+        (rate_limit.reset_at - Utc::now()).to_std().ok()
     }
 }
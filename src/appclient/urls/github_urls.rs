@@ -1,6 +1,7 @@
 pub enum GithubApiUrl {
     Releases,
     Repos,
+    Tags,
 }
 
 impl GithubApiUrl {
@@ -8,6 +9,7 @@ impl GithubApiUrl {
         match self {
             GithubApiUrl::Releases => "/releases",
             GithubApiUrl::Repos => "/repos",
+            GithubApiUrl::Tags => "/tags",
         }
     }
 
@@ -20,4 +22,13 @@ impl GithubApiUrl {
             Self::Releases.url()
         )
     }
+
+    pub fn repo_release_by_tag(owner: String, repo: String, tag: String) -> String {
+        format!(
+            "{}{}/{}",
+            Self::repo_releases(owner, repo),
+            Self::Tags.url(),
+            tag
+        )
+    }
 }
@@ -0,0 +1,598 @@
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use tokio::{join, spawn, time::sleep};
+use tracing::{error, info, warn};
+
+use crate::{
+    appclient::NymGithubClient,
+    constants::{NymExitCode, NymReleaseAssets},
+    updater::{NymAssetManager, NymUpdateResult, NymUpdater},
+    util::{NymConfigFileUtil, NymHistoryUtil, NymUpdateLock, NymUpdateRecord, NymUpdateStateUtil},
+};
+
+const DEFAULT_UPDATE_PERIOD_SECS: u64 = 3600;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "nym-updater",
+    version,
+    about = "Automatic updater for Nym nodes"
+)]
+pub struct AppCli {
+    #[command(subcommand)]
+    command: Option<AppCommand>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AppCommand {
+    /// Run the updater as a daemon, checking for updates periodically
+    Run {
+        /// Seconds to wait between update checks
+        #[arg(long, default_value_t = DEFAULT_UPDATE_PERIOD_SECS)]
+        period_secs: u64,
+    },
+    /// Report whether updates are available and exit
+    Check,
+    /// Update configured assets once and exit
+    Update {
+        /// Only update the given asset, e.g. nym-mixnode
+        #[arg(long)]
+        asset: Option<String>,
+    },
+    /// Install an exact release of an asset, upgrading or downgrading as needed
+    Install {
+        /// Asset to install, e.g. nym-mixnode
+        #[arg(long)]
+        asset: String,
+        /// Release tag to install, e.g. nym-binaries-v1.1.32
+        #[arg(long)]
+        tag: String,
+    },
+    /// Show installed and latest versions with systemd state of each asset
+    Status,
+    /// Show the actions `update` would take without changing anything
+    Plan {
+        /// Only plan the given asset, e.g. nym-mixnode
+        #[arg(long)]
+        asset: Option<String>,
+    },
+    /// Clear failed and bad release records of an asset so its releases are tried again
+    Retry {
+        /// Asset to clear, e.g. nym-mixnode
+        #[arg(long)]
+        asset: String,
+        /// Only clear the given release tag, e.g. nym-binaries-v1.1.32
+        #[arg(long)]
+        tag: Option<String>,
+    },
+    /// Show recorded update runs, oldest first
+    History {
+        /// Only show the given asset, e.g. nym-mixnode
+        #[arg(long)]
+        asset: Option<String>,
+        /// Only show the newest runs
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Print records as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+impl AppCli {
+    pub async fn execute(self) -> NymExitCode {
+        let command = self.command.unwrap_or(AppCommand::Run {
+            period_secs: DEFAULT_UPDATE_PERIOD_SECS,
+        });
+
+        match command {
+            AppCommand::Run { period_secs } => run_update_cron(period_secs).await,
+            AppCommand::Check => run_check().await,
+            AppCommand::Update { asset } => run_update(asset).await,
+            AppCommand::Install { asset, tag } => run_install(asset, tag).await,
+            AppCommand::Status => run_status().await,
+            AppCommand::Plan { asset } => run_plan(asset).await,
+            AppCommand::Retry { asset, tag } => run_retry(asset, tag),
+            AppCommand::History { asset, limit, json } => run_history(asset, limit, json),
+        }
+    }
+}
+
+pub async fn run_update_cron(period_secs: u64) -> NymExitCode {
+    let updater_task = spawn(async move {
+        'cron_loop: loop {
+            //Lock is only held while checking, so `update` and `install` may run between checks
+            let Some(update_lock) = acquire_update_lock() else {
+                run_sleep_period(period_secs).await;
+                continue 'cron_loop;
+            };
+
+            //Updates interrupted before this check are settled before any GitHub access
+            run_recovery().await;
+
+            let updater = match NymUpdater::init().await {
+                Ok(res) => res,
+                Err(e) => {
+                    error!("Failed to init updater: {:?}", e);
+                    drop(update_lock);
+                    run_sleep_until_next_check(period_secs).await;
+                    continue 'cron_loop;
+                }
+            };
+
+            join!(async {
+                for (asset, result) in updater.update_if_needed().await {
+                    log_update_result(&asset, &result);
+                }
+            });
+
+            drop(update_lock);
+            run_sleep_until_next_check(period_secs).await;
+        }
+    });
+
+    if let Err(e) = updater_task.await {
+        error!("Updater task failed: {:?}", e);
+        return NymExitCode::Failure;
+    }
+
+    NymExitCode::Success
+}
+
+pub async fn run_sleep_period(period_secs: u64) {
+    let period_duration = Duration::from_secs(period_secs);
+
+    sleep(period_duration).await;
+}
+
+//Waits for the GitHub rate limit to reset instead of the regular period when it is exhausted
+async fn run_sleep_until_next_check(period_secs: u64) {
+    match NymGithubClient::rate_limit_wait() {
+        Some(wait) => {
+            info!(
+                "GitHub rate limit exhausted, waiting {} seconds for reset",
+                wait.as_secs()
+            );
+            sleep(wait).await;
+        }
+        None => run_sleep_period(period_secs).await,
+    }
+}
+
+//Only one updater may change assets at a time, the lock is held until the returned guard is dropped
+fn acquire_update_lock() -> Option<NymUpdateLock> {
+    match NymUpdateStateUtil::acquire_lock() {
+        Ok(update_lock) => Some(update_lock),
+        Err(e) => {
+            error!("{}", e);
+            None
+        }
+    }
+}
+
+//Finishes or rolls back updates a killed updater left behind, needing only the config and systemd.
+//Returns whether every unfinished update was settled, None when the config could not be loaded
+async fn run_recovery() -> Option<bool> {
+    let asset_manager = match NymAssetManager::load() {
+        Ok(asset_manager) => asset_manager,
+        Err(e) => {
+            error!("Failed to load config for recovery: {}", e);
+            return None;
+        }
+    };
+
+    let mut is_settled = true;
+    for (asset, result) in asset_manager.recover_unfinished_updates().await {
+        log_update_result(&asset, &result);
+        if let NymUpdateResult::Failure(_) | NymUpdateResult::RolledBack(_) = result {
+            is_settled = false;
+        }
+    }
+
+    Some(is_settled)
+}
+
+async fn run_check() -> NymExitCode {
+    let updater = match NymUpdater::init().await {
+        Ok(res) => res,
+        Err(e) => {
+            error!("Failed to init updater: {:?}", e);
+            return NymExitCode::Failure;
+        }
+    };
+
+    let mut exit_code = NymExitCode::Success;
+    for (asset, target_tag) in updater.check_updates() {
+        match target_tag {
+            Some(tag) => {
+                println!("{}: update available to {}", asset.name(), tag);
+                exit_code = NymExitCode::UpdateAvailable;
+            }
+            None => println!("{}: up to date", asset.name()),
+        }
+    }
+
+    exit_code
+}
+
+async fn run_update(asset_name: Option<String>) -> NymExitCode {
+    let Some(_update_lock) = acquire_update_lock() else {
+        return NymExitCode::Failure;
+    };
+    let is_recovered = run_recovery().await != Some(false);
+    let updater = match NymUpdater::init().await {
+        Ok(res) => res,
+        Err(e) => {
+            error!("Failed to init updater: {:?}", e);
+            return NymExitCode::Failure;
+        }
+    };
+
+    let results = match asset_name {
+        Some(name) => {
+            let asset = match NymReleaseAssets::from_name(&name) {
+                Ok(asset) => asset,
+                Err(e) => {
+                    error!("{}", e);
+                    return NymExitCode::Failure;
+                }
+            };
+
+            let result = updater
+                .update_asset(&asset)
+                .await
+                .unwrap_or_else(NymUpdateResult::Failure);
+            vec![(asset, result)]
+        }
+        None => updater.update_if_needed().await,
+    };
+
+    let mut exit_code = if is_recovered {
+        NymExitCode::Success
+    } else {
+        NymExitCode::Failure
+    };
+    for (asset, result) in results {
+        log_update_result(&asset, &result);
+        if let NymUpdateResult::Failure(_) | NymUpdateResult::RolledBack(_) = result {
+            exit_code = NymExitCode::Failure;
+        }
+    }
+
+    exit_code
+}
+
+async fn run_install(asset_name: String, tag: String) -> NymExitCode {
+    let Some(_update_lock) = acquire_update_lock() else {
+        return NymExitCode::Failure;
+    };
+    let is_recovered = run_recovery().await != Some(false);
+    let updater = match NymUpdater::init().await {
+        Ok(res) => res,
+        Err(e) => {
+            error!("Failed to init updater: {:?}", e);
+            return NymExitCode::Failure;
+        }
+    };
+
+    let asset = match NymReleaseAssets::from_name(&asset_name) {
+        Ok(asset) => asset,
+        Err(e) => {
+            error!("{}", e);
+            return NymExitCode::Failure;
+        }
+    };
+
+    let result = updater
+        .install_asset_release(&asset, &tag)
+        .await
+        .unwrap_or_else(NymUpdateResult::Failure);
+    log_update_result(&asset, &result);
+
+    match result {
+        NymUpdateResult::Failure(_) | NymUpdateResult::RolledBack(_) => NymExitCode::Failure,
+        _ if !is_recovered => NymExitCode::Failure,
+        _ => NymExitCode::Success,
+    }
+}
+
+async fn run_status() -> NymExitCode {
+    let updater = match NymUpdater::init().await {
+        Ok(res) => res,
+        Err(e) => {
+            error!("Failed to init updater: {:?}", e);
+            return NymExitCode::Failure;
+        }
+    };
+
+    //A transaction of a running updater is still being applied, not left behind
+    let is_locked = NymUpdateStateUtil::is_locked();
+    for status in updater.assets_status().await {
+        let installed_tag = status
+            .installed
+            .as_ref()
+            .map(|installed| installed.release_tag.as_str())
+            .unwrap_or("-");
+
+        println!("{}", status.asset.name());
+        println!("  systemd state:   {:?}", status.state);
+        println!(
+            "  running version: {}",
+            status.running_version.as_deref().unwrap_or("-")
+        );
+        println!("  installed tag:   {}", installed_tag);
+        println!("  latest tag:      {}", status.latest_release_tag);
+        println!(
+            "  version pin:     {}",
+            status.version_pin.as_deref().unwrap_or("-")
+        );
+        println!("  target tag:      {}", status.target_release_tag);
+        println!("  auto update:     {}", status.auto_update);
+        println!("  update policy:   {}", status.update_policy.as_str());
+        if let Some(soaking_until) = status.soaking_until {
+            println!("  soaking until:   {}", soaking_until);
+        }
+        println!(
+            "  held back:       {}",
+            status.held_release_tag.as_deref().unwrap_or("-")
+        );
+        if let Some(failed_attempt) = &status.target_failed_attempt {
+            let next_attempt = if failed_attempt.quarantined {
+                "after retry".to_string()
+            } else {
+                failed_attempt.retry_at.to_string()
+            };
+            println!(
+                "  failed attempts: {}, next attempt {}, last error: {}",
+                failed_attempt.failures, next_attempt, failed_attempt.last_error
+            );
+        }
+        if let Some(transaction) = &status.unfinished_update {
+            let update_kind = if is_locked {
+                "update in progress"
+            } else {
+                "unfinished update"
+            };
+            println!(
+                "  {}: {} at phase {} since {}",
+                update_kind,
+                transaction.release_tag,
+                transaction.phase.as_str(),
+                transaction.started_at
+            );
+        }
+        if !status.bad_release_tags.is_empty() {
+            println!("  bad releases:    {}", status.bad_release_tags.join(", "));
+        }
+        println!("  update available: {}", status.update_available);
+    }
+
+    NymExitCode::Success
+}
+
+async fn run_plan(asset_name: Option<String>) -> NymExitCode {
+    let updater = match NymUpdater::init().await {
+        Ok(res) => res,
+        Err(e) => {
+            error!("Failed to init updater: {:?}", e);
+            return NymExitCode::Failure;
+        }
+    };
+
+    let asset = match asset_name.as_deref().map(NymReleaseAssets::from_name) {
+        Some(Ok(asset)) => Some(asset),
+        Some(Err(e)) => {
+            error!("{}", e);
+            return NymExitCode::Failure;
+        }
+        None => None,
+    };
+
+    let plans = match updater.plan_updates(asset.as_ref()).await {
+        Ok(plans) => plans,
+        Err(e) => {
+            error!("Failed to plan updates: {}", e);
+            return NymExitCode::Failure;
+        }
+    };
+
+    let mut exit_code = NymExitCode::Success;
+    for plan in plans {
+        println!("{} -> {}", plan.asset.name(), plan.release_tag);
+        for (name, value) in &plan.facts {
+            println!("  {}: {}", name, value);
+        }
+        if !plan.actions.is_empty() {
+            println!("  actions:");
+            for (index, action) in plan.actions.iter().enumerate() {
+                println!("    {}. {}", index + 1, action);
+            }
+        }
+
+        let result = match &plan.result {
+            NymUpdateResult::Success => {
+                exit_code = NymExitCode::UpdateAvailable;
+                "update would be applied".to_string()
+            }
+            NymUpdateResult::NotNecessary => "no update needed".to_string(),
+            NymUpdateResult::Disabled => "auto update disabled".to_string(),
+            NymUpdateResult::Held(tag) => format!("{} is held back by update policy", tag),
+            NymUpdateResult::Soaking(tag, soak_end) => {
+                format!("{} is pending until {}, release is too new", tag, soak_end)
+            }
+            NymUpdateResult::BadRelease(tag) => format!("{} is recorded as bad", tag),
+            NymUpdateResult::Quarantined(tag) => {
+                format!("{} failed too often, run retry to try it again", tag)
+            }
+            NymUpdateResult::BackingOff(tag, retry_at) => {
+                format!("{} failed before, next attempt after {}", tag, retry_at)
+            }
+            NymUpdateResult::RolledBack(msg) | NymUpdateResult::Failure(msg) => {
+                format!("update would fail: {}", msg)
+            }
+        };
+        println!("  result: {}", result);
+    }
+
+    exit_code
+}
+
+fn run_retry(asset_name: String, tag: Option<String>) -> NymExitCode {
+    let Some(_update_lock) = acquire_update_lock() else {
+        return NymExitCode::Failure;
+    };
+    let asset = match NymReleaseAssets::from_name(&asset_name) {
+        Ok(asset) => asset,
+        Err(e) => {
+            error!("{}", e);
+            return NymExitCode::Failure;
+        }
+    };
+
+    match NymConfigFileUtil::clear_failed_releases(&asset, tag.as_deref()) {
+        Ok(cleared) => {
+            println!(
+                "{}: cleared {} failed release records",
+                asset.name(),
+                cleared
+            );
+            NymExitCode::Success
+        }
+        Err(e) => {
+            error!("Failed to clear failed releases: {}", e);
+            NymExitCode::Failure
+        }
+    }
+}
+
+fn run_history(asset_name: Option<String>, limit: Option<usize>, json: bool) -> NymExitCode {
+    let asset = match asset_name.as_deref().map(NymReleaseAssets::from_name) {
+        Some(Ok(asset)) => Some(asset),
+        Some(Err(e)) => {
+            error!("{}", e);
+            return NymExitCode::Failure;
+        }
+        None => None,
+    };
+
+    let mut records = match NymHistoryUtil::read_records() {
+        Ok(records) => records,
+        Err(e) => {
+            error!("Failed to read update history: {}", e);
+            return NymExitCode::Failure;
+        }
+    };
+    if let Some(asset) = &asset {
+        records.retain(|record| record.asset == asset.name());
+    }
+    if let Some(limit) = limit {
+        records.drain(..records.len().saturating_sub(limit));
+    }
+
+    if json {
+        return match serde_json::to_string_pretty(&records) {
+            Ok(records_json) => {
+                println!("{}", records_json);
+                NymExitCode::Success
+            }
+            Err(e) => {
+                error!("Failed to serialize update history: {}", e);
+                NymExitCode::Failure
+            }
+        };
+    }
+
+    print_history_table(&records);
+    NymExitCode::Success
+}
+
+fn print_history_table(records: &[NymUpdateRecord]) {
+    let header = [
+        "STARTED", "ASSET", "FROM", "TO", "TAG", "OUTCOME", "DOWNTIME", "ERROR",
+    ]
+    .map(|column| column.to_string());
+    let rows = records
+        .iter()
+        .map(|record| {
+            let mut outcome = record.outcome.as_str().to_string();
+            if record.recovered {
+                outcome.push_str(" (recovered)");
+            }
+            [
+                record.started_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+                record.asset.clone(),
+                record.from_version.clone().unwrap_or("-".to_string()),
+                record.to_version.clone().unwrap_or("-".to_string()),
+                record.to_release_tag.clone(),
+                outcome,
+                record
+                    .downtime_secs
+                    .map(format_duration_secs)
+                    .unwrap_or("-".to_string()),
+                record.error.clone().unwrap_or_default(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let mut widths = header.clone().map(|column| column.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+}
+
+fn format_duration_secs(secs: i64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+fn log_update_result(asset: &NymReleaseAssets, result: &NymUpdateResult) {
+    let asset_name = asset.name();
+    match result {
+        NymUpdateResult::Success => info!("Updater succeeded for {}", asset_name),
+        NymUpdateResult::NotNecessary => info!("No update needed for {}", asset_name),
+        NymUpdateResult::Disabled => info!("Auto update disabled for {}", asset_name),
+        NymUpdateResult::Held(tag) => info!(
+            "Update of {} to {} is pending, held back by update policy",
+            asset_name, tag
+        ),
+        NymUpdateResult::Soaking(tag, soak_end) => info!(
+            "Update of {} to {} is pending until {}, release is too new",
+            asset_name, tag, soak_end
+        ),
+        NymUpdateResult::BadRelease(tag) => warn!(
+            "Release {} of {} is recorded as bad after crash looping, not updating",
+            tag, asset_name
+        ),
+        NymUpdateResult::Quarantined(tag) => warn!(
+            "Release {} of {} failed too often, not updating until `retry` is run",
+            tag, asset_name
+        ),
+        NymUpdateResult::BackingOff(tag, retry_at) => info!(
+            "Update of {} to {} failed before, next attempt after {}",
+            asset_name, tag, retry_at
+        ),
+        NymUpdateResult::RolledBack(msg) => {
+            error!(
+                "Updater rolled back {} to previous release: {}",
+                asset_name, msg
+            )
+        }
+        NymUpdateResult::Failure(msg) => error!("Updater failed for {}: {}", asset_name, msg),
+    }
+}
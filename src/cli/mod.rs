@@ -0,0 +1,3 @@
+mod app_cli;
+
+pub use app_cli::*;
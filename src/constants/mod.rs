@@ -1,3 +1,5 @@
+mod nym_exit_code;
 mod nym_release_assets;
 
+pub use nym_exit_code::*;
 pub use nym_release_assets::*;
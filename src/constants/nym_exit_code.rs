@@ -0,0 +1,24 @@
+use std::process::ExitCode;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NymExitCode {
+    Success,
+    Failure,
+    UpdateAvailable,
+}
+
+impl NymExitCode {
+    pub fn code(&self) -> u8 {
+        match self {
+            NymExitCode::Success => 0,
+            NymExitCode::Failure => 1,
+            NymExitCode::UpdateAvailable => 2,
+        }
+    }
+}
+
+impl From<NymExitCode> for ExitCode {
+    fn from(value: NymExitCode) -> Self {
+        ExitCode::from(value.code())
+    }
+}
@@ -16,6 +16,19 @@ impl NymReleaseAssets {
             .collect()
     }
 
+    pub fn from_name(name: &str) -> Result<NymReleaseAssets, String> {
+        NymReleaseAssets::get_all()
+            .into_iter()
+            .find(|asset| asset.name() == name)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown asset name '{}', expected one of {:?}",
+                    name,
+                    NymReleaseAssets::get_all_as_string()
+                )
+            })
+    }
+
     pub fn name(&self) -> &str {
         match self {
             NymReleaseAssets::MixNode => "nym-mixnode",
@@ -1,11 +1,12 @@
-use std::time::Duration;
+use std::process::ExitCode;
 
-use tokio::{join, spawn, time::sleep};
-use tracing::{error, info};
+use clap::Parser;
+use tracing::info;
 
-use crate::{updater::NymUpdater, util::init_logger};
+use crate::{cli::AppCli, constants::NymExitCode, util::init_logger};
 
 mod appclient;
+mod cli;
 mod cmd;
 mod constants;
 mod updater;
@@ -15,46 +16,19 @@ const LOG_FILE_DIR: &str = "./logs";
 const LOG_FILE_PREFIX: &str = "app.log";
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let _guard = init_logger(Some(LOG_FILE_DIR), LOG_FILE_PREFIX)?;
-    info!("Starting app");
-    run_update_cron().await;
-    info!("Stopping app");
-    Ok(())
-}
-
-pub async fn run_update_cron() {
-    let updater_task = spawn(async move {
-        'cron_loop: loop {
-            let updater = match NymUpdater::init().await {
-                Ok(res) => res,
-                Err(e) => {
-                    error!("Failed to init updater: {:?}", e);
-                    run_sleep_period().await;
-                    continue 'cron_loop;
-                }
-            };
-
-            join!(async {
-                use updater::NymUpdateResult::*;
-                match updater.update_if_needed().await {
-                    Success => info!("Updater succeeded"),
-                    NotNecessary => info!("No update needed"),
-                    Failure(msg) => error!("Updater failed: {}", msg),
-                }
-            });
-
-            run_sleep_period().await;
+async fn main() -> ExitCode {
+    let cli = AppCli::parse();
+
+    let _guard = match init_logger(Some(LOG_FILE_DIR), LOG_FILE_PREFIX) {
+        Ok(guard) => guard,
+        Err(e) => {
+            eprintln!("Failed to init logger: {}", e);
+            return NymExitCode::Failure.into();
         }
-    });
+    };
 
-    if let Err(e) = updater_task.await {
-        error!("Updater task failed: {:?}", e);
-    }
-}
-
-pub async fn run_sleep_period() {
-    let period_duration = Duration::from_secs(3600);
-
-    sleep(period_duration).await;
+    info!("Starting app");
+    let exit_code = cli.execute().await;
+    info!("Stopping app with exit code {}", exit_code.code());
+    exit_code.into()
 }
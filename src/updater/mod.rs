@@ -1,3 +1,5 @@
+mod nym_asset_manager;
 mod nym_updater;
 
+pub use nym_asset_manager::*;
 pub use nym_updater::*;
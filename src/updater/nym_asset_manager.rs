@@ -0,0 +1,584 @@
+use chrono::Utc;
+use cmd_lib::run_fun;
+use tracing::{error, info, warn};
+
+use crate::{
+    constants::NymReleaseAssets,
+    util::{
+        NymAssetStoreUtil, NymAssetUpdateConfig, NymBadRelease, NymConfigFileUtil,
+        NymHealthCheckError, NymHealthCheckUtil, NymHistoryUtil, NymInstallRootUtil,
+        NymInstalledAsset, NymReleaseConfig, NymRollbackRecord, NymSystemdFileUtil,
+        NymUpdateOutcome, NymUpdatePhase, NymUpdateRecord, NymUpdateStateUtil,
+        NymUpdateTransaction,
+    },
+};
+
+use super::{AssetState, NymUpdateResult};
+
+//Everything that changes a local asset, driven by the config file and systemd only. It needs no
+//GitHub access, so unfinished updates are recovered even while GitHub is unreachable
+#[derive(Debug)]
+pub struct NymAssetManager {
+    local_release_config: NymReleaseConfig,
+}
+
+impl NymAssetManager {
+    pub fn new(local_release_config: NymReleaseConfig) -> Self {
+        Self {
+            local_release_config,
+        }
+    }
+
+    pub fn load() -> Result<Self, String> {
+        Ok(Self::new(NymConfigFileUtil::read_config_file()?))
+    }
+
+    pub fn release_config(&self) -> &NymReleaseConfig {
+        &self.local_release_config
+    }
+
+    pub async fn current_asset_state(
+        &self,
+        asset: &NymReleaseAssets,
+    ) -> Result<AssetState, String> {
+        let asset_name = asset.name();
+        let systemd_manager = NymSystemdFileUtil::new(asset.clone());
+        let load_state = systemd_manager.show_property("LoadState")?;
+        let active_state = systemd_manager.show_property("ActiveState")?;
+
+        let asset_state = match (load_state.as_str(), active_state.as_str()) {
+            ("not-found", _) => AssetState::NotAvailable,
+            (_, "inactive" | "failed") => AssetState::Stopped,
+            //Activating, deactivating and reloading units, e.g. one crash looping under
+            //`Restart=always`, have to be stopped before an update just like active ones
+            _ => AssetState::Running,
+        };
+
+        info!(
+            "{} state on systemd is {:?} ({})",
+            asset_name, asset_state, active_state
+        );
+        Ok(asset_state)
+    }
+
+    pub async fn stop_asset_service(&self, asset: &NymReleaseAssets) -> Result<(), String> {
+        let asset_name = asset.name();
+        info!("Stopping {}...", asset_name);
+        run_fun!(service $asset_name stop)
+            .map_err(|e| format!("Error while stopping {} with {} error", asset_name, e))?;
+        Ok(())
+    }
+
+    pub async fn systemd_asset_path(&self, asset: &NymReleaseAssets) -> Result<String, String> {
+        NymSystemdFileUtil::new(asset.clone())
+            .current_exec_start_path()
+            .await
+    }
+
+    pub async fn asset_build_version(
+        &self,
+        asset: &NymReleaseAssets,
+        bin_path: String,
+    ) -> Result<String, String> {
+        let res = run_fun!($bin_path --version | grep "Build Version" | awk "{print $3}").map_err(
+            |e| {
+                format!(
+                    "Error while getting {} version with {} error",
+                    asset.name(),
+                    e
+                )
+            },
+        )?;
+
+        Ok(res)
+    }
+
+    pub async fn current_asset_version(&self, asset: &NymReleaseAssets) -> Result<String, String> {
+        let asset_name = asset.name();
+        let asset_path = self.systemd_asset_path(asset).await?.trim().to_string();
+        let res = self.asset_build_version(asset, asset_path).await?;
+        info!("Current {} version is {}", asset_name, res);
+        Ok(res)
+    }
+
+    pub async fn node_id(&self, asset: &NymReleaseAssets) -> Result<String, String> {
+        let asset_name = asset.name();
+        let id = run_fun!(systemctl cat $asset_name | grep -o -- "--id [^ ]*" | awk "{print $2}")
+            .map_err(|e| format!("Error while getting {} id with {} error", asset_name, e))?;
+
+        Ok(id)
+    }
+
+    pub async fn reload_systemd_daemon(&self) -> Result<(), String> {
+        info!("Reloading systemd daemon...");
+        run_fun!(sudo systemctl daemon-reload)
+            .map_err(|e| format!("Error while reloading systemd daemon with {} error", e))?;
+        info!("Systemd daemon reloaded");
+        Ok(())
+    }
+
+    pub async fn update_systemd_file(
+        &self,
+        asset: NymReleaseAssets,
+        new_exec_path: String,
+    ) -> Result<(), String> {
+        info!("Updating {} systemd file...", asset.name());
+
+        let systemd_manager = NymSystemdFileUtil::new(asset.clone());
+
+        let version = self
+            .asset_build_version(&asset, new_exec_path.clone())
+            .await?;
+
+        systemd_manager
+            .update_exec_start_prop(new_exec_path.clone())
+            .await?;
+
+        systemd_manager.update_description_prop(version).await?;
+
+        systemd_manager.systemd_reload()?;
+        Ok(())
+    }
+
+    pub fn install_root(&self, asset: &NymReleaseAssets) -> NymInstallRootUtil {
+        NymInstallRootUtil::new(asset.clone(), &self.local_release_config.install)
+    }
+
+    pub async fn start_asset_service(&self, asset: &NymReleaseAssets) -> Result<(), String> {
+        let asset_name = asset.name();
+        info!("Starting {}...", asset_name);
+        run_fun!(systemctl start $asset_name)
+            .map_err(|e| format!("Error while restarting {} with {} error", asset_name, e))?;
+
+        info!("Successfully started {}", asset_name);
+        Ok(())
+    }
+
+    pub async fn public_ip(&self) -> Result<String, String> {
+        run_fun!(curl ipinfo.io | jq -r ".ip")
+            .map_err(|e| format!("Error while getting ip address with {} error", e))
+    }
+
+    pub async fn init_args(&self, asset: &NymReleaseAssets) -> Result<Vec<String>, String> {
+        let id = self.node_id(asset).await?;
+        let ip = self.public_ip().await?;
+
+        let args = match asset {
+            NymReleaseAssets::MixNode => vec!["--id".to_string(), id, "--host".to_string(), ip],
+            //Gateway listens on every interface and announces its public ip to the network
+            NymReleaseAssets::Gateway => vec![
+                "--id".to_string(),
+                id,
+                "--host".to_string(),
+                "0.0.0.0".to_string(),
+                "--announce-host".to_string(),
+                ip,
+            ],
+        };
+
+        Ok(args)
+    }
+
+    pub async fn init_asset_with_path(
+        &self,
+        asset: &NymReleaseAssets,
+        path: String,
+    ) -> Result<(), String> {
+        let asset_name = asset.name();
+        let init_args = self.init_args(asset).await?;
+
+        info!("Initing {} with {}", asset_name, init_args.join(" "));
+        let res = run_fun!(sudo $path init $[init_args])
+            .map_err(|e| format!("Error while initing {} with {} error", asset_name, e))?;
+        info!("Init result: {}", res);
+        Ok(())
+    }
+
+    //Takes every phase after the service was stopped that is not reached yet, rolling back on failure
+    pub async fn complete_transaction(
+        &self,
+        asset: &NymReleaseAssets,
+        transaction: &mut NymUpdateTransaction,
+    ) -> NymUpdateResult {
+        let asset_name = asset.name();
+
+        //Service only counts as updated once it passes the health gate
+        let update_err = if transaction.phase >= NymUpdatePhase::HealthChecked {
+            None
+        } else {
+            let health_check = self.health_check(asset);
+            match self.apply_update(asset, transaction, &health_check).await {
+                Err(e) => Some(e),
+                Ok(restarts_before) => match health_check.verify(restarts_before).await {
+                    Ok(()) => transaction
+                        .advance(asset, NymUpdatePhase::HealthChecked)
+                        .err(),
+                    Err(NymHealthCheckError::CrashLoop(e)) => {
+                        self.record_bad_release(asset, &transaction.release_tag, &e);
+                        Some(e)
+                    }
+                    Err(NymHealthCheckError::Unhealthy(e)) => Some(e),
+                },
+            }
+        };
+
+        if let Some(e) = update_err {
+            error!(
+                "{} update failed with {} error, rolling back",
+                asset_name, e
+            );
+
+            let rollback_result = self.rollback(asset, transaction).await;
+            transaction.rollback = Some(NymRollbackRecord {
+                release_tag: transaction.previous_version.clone(),
+                exec_start: transaction.unit_snapshot.exec_start.clone(),
+                succeeded: rollback_result.is_ok(),
+                error: rollback_result.as_ref().err().cloned(),
+                finished_at: Utc::now(),
+            });
+
+            return match rollback_result {
+                Ok(()) => NymUpdateResult::RolledBack(e),
+                Err(rollback_err) => NymUpdateResult::Failure(format!(
+                    "{}, rollback also failed with {} error",
+                    e, rollback_err
+                )),
+            };
+        }
+
+        let target_version = match &transaction.target_version {
+            Some(target_version) => Ok(target_version.clone()),
+            None => {
+                self.asset_build_version(asset, transaction.target_path.clone())
+                    .await
+            }
+        };
+        let recorded = target_version.and_then(|target_version| {
+            self.record_installed_asset(
+                asset,
+                &transaction.release_tag,
+                target_version,
+                transaction.target_path.clone(),
+            )
+        });
+        if let Err(e) = recorded {
+            return NymUpdateResult::Failure(e);
+        }
+        self.prune_asset_versions(asset);
+
+        NymUpdateResult::Success
+    }
+
+    pub fn record_history(
+        &self,
+        transaction: &NymUpdateTransaction,
+        result: Result<&NymUpdateResult, &str>,
+        recovered: bool,
+    ) {
+        let (outcome, error) = match result {
+            Ok(NymUpdateResult::Success) => (NymUpdateOutcome::Success, None),
+            Ok(NymUpdateResult::NotNecessary) => (NymUpdateOutcome::NotNecessary, None),
+            Ok(NymUpdateResult::RolledBack(e)) => (NymUpdateOutcome::RolledBack, Some(e.clone())),
+            Ok(NymUpdateResult::Failure(e)) => (NymUpdateOutcome::Failure, Some(e.clone())),
+            Err(e) => (NymUpdateOutcome::Failure, Some(e.to_string())),
+            //Skipped updates never start a transaction
+            Ok(_) => return,
+        };
+
+        let record = NymUpdateRecord::from_transaction(transaction, outcome, error, recovered);
+        self.append_history(&record);
+    }
+
+    //History is informational, failing to write it never fails an update
+    pub fn append_history(&self, record: &NymUpdateRecord) {
+        if let Err(e) = NymHistoryUtil::append_record(record) {
+            error!("Failed to record {} update history: {}", record.asset, e);
+        }
+    }
+
+    //Pruning failures never fail an update that is already applied
+    pub fn prune_asset_versions(&self, asset: &NymReleaseAssets) {
+        let keep_versions = self.local_release_config.install.keep_versions;
+        match self.install_root(asset).prune_versions(keep_versions) {
+            Ok(pruned_tags) => {
+                for release_tag in pruned_tags {
+                    NymAssetStoreUtil::remove_asset(&release_tag, asset.name());
+                }
+            }
+            Err(e) => error!("Failed to prune {} versions: {}", asset.name(), e),
+        }
+    }
+
+    //Returns the NRestarts value the health gate counts new restarts from
+    pub async fn apply_update(
+        &self,
+        asset: &NymReleaseAssets,
+        transaction: &mut NymUpdateTransaction,
+        health_check: &NymHealthCheckUtil,
+    ) -> Result<u64, String> {
+        if transaction.phase < NymUpdatePhase::Initialized {
+            self.init_asset_with_path(asset, transaction.target_path.clone())
+                .await?;
+            transaction.advance(asset, NymUpdatePhase::Initialized)?;
+        }
+
+        if transaction.phase < NymUpdatePhase::UnitRewritten {
+            let install_root = self.install_root(asset);
+            install_root.switch_current(&transaction.release_tag)?;
+            self.update_systemd_file(asset.clone(), install_root.current_bin_path())
+                .await?;
+            transaction.advance(asset, NymUpdatePhase::UnitRewritten)?;
+        }
+
+        //Unit started before a crash is measured from now on
+        if transaction.phase >= NymUpdatePhase::Started {
+            return health_check.restart_count();
+        }
+
+        //systemd resets NRestarts on a manual start, so every restart counted from here is new
+        self.start_asset_service(asset).await?;
+        transaction.advance(asset, NymUpdatePhase::Started)?;
+        Ok(0)
+    }
+
+    pub fn health_check(&self, asset: &NymReleaseAssets) -> NymHealthCheckUtil {
+        let health_url = self
+            .asset_config(asset)
+            .ok()
+            .and_then(|asset_config| asset_config.health_url.clone());
+        NymHealthCheckUtil::new(
+            asset.clone(),
+            self.local_release_config.health_check.clone(),
+            health_url,
+        )
+    }
+
+    pub async fn rollback(
+        &self,
+        asset: &NymReleaseAssets,
+        transaction: &NymUpdateTransaction,
+    ) -> Result<(), String> {
+        let asset_name = asset.name();
+        let unit_snapshot = &transaction.unit_snapshot;
+        info!(
+            "Rolling back {} to {}...",
+            asset_name, unit_snapshot.exec_start
+        );
+
+        //New binary may be crash looping, so make sure it is stopped before restoring
+        if let Err(e) = self.stop_asset_service(asset).await {
+            info!("{} could not be stopped before rollback: {}", asset_name, e);
+        }
+
+        if let Some(previous_version) = &transaction.previous_version {
+            self.install_root(asset).switch_current(previous_version)?;
+        }
+
+        let systemd_manager = NymSystemdFileUtil::new(asset.clone());
+        systemd_manager.restore_unit_snapshot(unit_snapshot)?;
+        systemd_manager.systemd_reload()?;
+
+        if transaction.was_running {
+            run_fun!(systemctl start $asset_name).map_err(|e| {
+                format!(
+                    "Error while starting previous {} with {} error",
+                    asset_name, e
+                )
+            })?;
+        }
+
+        info!("{} rolled back to previous release", asset_name);
+        Ok(())
+    }
+
+    pub fn record_installed_asset(
+        &self,
+        asset: &NymReleaseAssets,
+        release_tag: &str,
+        build_version: String,
+        bin_path: String,
+    ) -> Result<(), String> {
+        let installed = NymInstalledAsset {
+            release_tag: release_tag.to_string(),
+            build_version,
+            bin_path,
+            installed_at: Utc::now(),
+        };
+
+        info!(
+            "Recording {} as installed on release {}",
+            asset.name(),
+            installed.release_tag
+        );
+
+        NymConfigFileUtil::update_installed_asset(asset, installed)
+            .map_err(|e| format!("Error while updating installed asset with {} error", e))
+    }
+
+    pub fn record_bad_release(&self, asset: &NymReleaseAssets, release_tag: &str, reason: &str) {
+        warn!(
+            "Recording {} release {} as bad, it will not be installed automatically again",
+            asset.name(),
+            release_tag
+        );
+
+        let bad_release = NymBadRelease {
+            release_tag: release_tag.to_string(),
+            reason: reason.to_string(),
+            recorded_at: Utc::now(),
+        };
+        if let Err(e) = NymConfigFileUtil::add_bad_release(asset, bad_release) {
+            error!("Failed to record bad release with {} error", e);
+        }
+    }
+
+    //Only failures of the release itself count against it, i.e. a rejected download or a failure
+    //while applying it. Transport errors and setup problems before the stop say nothing about it
+    pub fn record_attempt(
+        &self,
+        asset: &NymReleaseAssets,
+        release_tag: &str,
+        counts_against_release: bool,
+        result: Result<&NymUpdateResult, &str>,
+    ) {
+        match result {
+            _ if !counts_against_release => {}
+            Ok(NymUpdateResult::Failure(e)) | Ok(NymUpdateResult::RolledBack(e)) => {
+                self.record_failed_attempt(asset, release_tag, e)
+            }
+            Err(e) => self.record_failed_attempt(asset, release_tag, e),
+            Ok(NymUpdateResult::Success) => self.clear_failed_attempts(asset, release_tag),
+            Ok(_) => {}
+        }
+    }
+
+    pub fn record_failed_attempt(&self, asset: &NymReleaseAssets, release_tag: &str, error: &str) {
+        let failed_attempt = match NymConfigFileUtil::add_failed_attempt(asset, release_tag, error)
+        {
+            Ok(failed_attempt) => failed_attempt,
+            Err(e) => {
+                error!("Failed to record failed attempt with {} error", e);
+                return;
+            }
+        };
+
+        let failure_policy = &self.local_release_config.failure_policy;
+        if failure_policy.is_quarantined(&failed_attempt) {
+            warn!(
+                "{} release {} failed {} times, it will not be tried again until `retry` is run or a newer release appears",
+                asset.name(),
+                release_tag,
+                failed_attempt.failures
+            );
+        } else {
+            info!(
+                "{} release {} failed {} times, next attempt after {}",
+                asset.name(),
+                release_tag,
+                failed_attempt.failures,
+                failure_policy.retry_at(&failed_attempt)
+            );
+        }
+    }
+
+    pub fn clear_failed_attempts(&self, asset: &NymReleaseAssets, release_tag: &str) {
+        let has_records = self.asset_config(asset).is_ok_and(|asset_config| {
+            asset_config.failed_attempt(release_tag).is_some()
+                || asset_config.is_bad_release(release_tag)
+        });
+        if !has_records {
+            return;
+        }
+
+        if let Err(e) = NymConfigFileUtil::clear_failed_releases(asset, Some(release_tag)) {
+            error!("Failed to clear failed attempts with {} error", e);
+        }
+    }
+
+    pub fn asset_config(&self, asset: &NymReleaseAssets) -> Result<&NymAssetUpdateConfig, String> {
+        self.configured_assets()
+            .into_iter()
+            .find(|(configured, _)| configured.name() == asset.name())
+            .map(|(_, asset_config)| asset_config)
+            .ok_or_else(|| format!("{} is not defined in config file", asset.name()))
+    }
+
+    pub fn configured_assets(&self) -> Vec<(NymReleaseAssets, &NymAssetUpdateConfig)> {
+        self.local_release_config
+            .assets
+            .iter()
+            .filter_map(|asset_config| match asset_config.asset() {
+                Ok(asset) => Some((asset, asset_config)),
+                //Config file is validated on read, so asset names are known here
+                Err(e) => {
+                    error!("{}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    //Finishes or rolls back updates a killed updater left behind, depending on the phase they reached
+    pub async fn recover_unfinished_updates(&self) -> Vec<(NymReleaseAssets, NymUpdateResult)> {
+        let mut results = vec![];
+        for (asset, _) in self.configured_assets() {
+            let mut transaction = match NymUpdateStateUtil::read_transaction(&asset) {
+                Ok(Some(transaction)) => transaction,
+                Ok(None) => continue,
+                Err(e) => {
+                    results.push((asset, NymUpdateResult::Failure(e)));
+                    continue;
+                }
+            };
+
+            warn!(
+                "Found unfinished {} update to {} at phase {}, recovering...",
+                asset.name(),
+                transaction.release_tag,
+                transaction.phase.as_str()
+            );
+            let was_applying = transaction.phase >= NymUpdatePhase::Stopped;
+            let result = self.recover_transaction(&asset, &mut transaction).await;
+            self.record_history(&transaction, Ok(&result), true);
+            self.record_attempt(&asset, &transaction.release_tag, was_applying, Ok(&result));
+            if let Err(e) = NymUpdateStateUtil::remove_transaction(&asset) {
+                error!("Failed to clear {} update state: {}", asset.name(), e);
+            }
+
+            results.push((asset, result));
+        }
+
+        results
+    }
+
+    pub async fn recover_transaction(
+        &self,
+        asset: &NymReleaseAssets,
+        transaction: &mut NymUpdateTransaction,
+    ) -> NymUpdateResult {
+        if transaction.phase >= NymUpdatePhase::Stopped {
+            //New release is verified and installed, so finishing it is as safe as the health gate
+            return self.complete_transaction(asset, transaction).await;
+        }
+
+        //Nothing was changed before the stop, only make sure the service runs again
+        let interrupted = format!(
+            "Update to {} was interrupted at phase {}",
+            transaction.release_tag,
+            transaction.phase.as_str()
+        );
+        let is_running = matches!(
+            self.current_asset_state(asset).await,
+            Ok(AssetState::Running)
+        );
+        if !transaction.was_running || is_running {
+            info!("{}, nothing to roll back", interrupted);
+            return NymUpdateResult::NotNecessary;
+        }
+
+        match self.start_asset_service(asset).await {
+            Ok(()) => NymUpdateResult::RolledBack(interrupted),
+            Err(e) => NymUpdateResult::Failure(format!("{}, {}", interrupted, e)),
+        }
+    }
+}
@@ -1,299 +1,1105 @@
-use cmd_lib::run_fun;
-use reqwest::Version;
-use tracing::info;
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+use tracing::{error, info, warn};
 
 use crate::{
-    appclient::{GithubRelease, NymGithubClient},
+    appclient::{GithubRelease, NymGithubClient, NymReleaseError},
     cmd::AppCmd,
     constants::NymReleaseAssets,
-    util::{NymConfigFileUtil, NymReleaseConfig, NymSystemdFileUtil},
+    updater::NymAssetManager,
+    util::{
+        NymAssetStoreUtil, NymAssetUpdateConfig, NymChecksumUtil, NymConfigFileUtil,
+        NymInstalledAsset, NymPhaseRecord, NymSignatureUtil, NymSystemdFileUtil, NymUpdatePhase,
+        NymUpdatePolicy, NymUpdateRecord, NymUpdateStateUtil, NymUpdateTransaction, NymVersionUtil,
+    },
 };
 
 #[derive(Debug)]
 pub struct NymUpdater {
     nym_github_client: NymGithubClient,
     latest_github_release: GithubRelease,
-    local_release_config: NymReleaseConfig,
+    pinned_github_releases: Vec<GithubRelease>,
+    release_tag_regex: Regex,
+    asset_manager: NymAssetManager,
 }
 
 impl NymUpdater {
     pub async fn init() -> Result<Self, String> {
-        let latest_release = NymGithubClient::new().latest_nym_release().await?;
         let current_release = NymConfigFileUtil::read_config_file()?;
-        let nym_github_client = NymGithubClient::new();
+        let channel = current_release.release_channel;
+        let nym_github_client = NymGithubClient::new(&current_release.upstream)?;
+        let latest_release = nym_github_client.latest_nym_release(&channel).await?;
+        let release_tag_regex = current_release.upstream.tag_regex()?;
+
+        let mut pinned_releases: Vec<GithubRelease> = vec![];
+        for asset_config in &current_release.assets {
+            let Some(version_pin) = &asset_config.version_pin else {
+                continue;
+            };
+            if pinned_releases.iter().any(|r| &r.tag_name == version_pin) {
+                continue;
+            }
+
+            info!("{} is pinned to release {}", asset_config.name, version_pin);
+            let pinned_release = nym_github_client
+                .nym_release_by_tag(version_pin, &channel)
+                .await
+                .map_err(|e| {
+                    format!(
+                        "Error while getting pinned release {} of {} with {} error",
+                        version_pin, asset_config.name, e
+                    )
+                })?;
+            pinned_releases.push(pinned_release);
+        }
 
         Ok(Self {
             latest_github_release: latest_release,
-            local_release_config: current_release,
+            pinned_github_releases: pinned_releases,
+            release_tag_regex,
+            asset_manager: NymAssetManager::new(current_release),
             nym_github_client,
         })
     }
 
-    pub async fn current_asset_state(
+    //Downloads the release asset into the store, unless a verified copy is stored already
+    async fn download_release(
         &self,
         asset: &NymReleaseAssets,
-    ) -> Result<AssetState, String> {
-        let asset_name = asset.name();
-        let state = run_fun!(systemctl show -p ActiveState --value $asset_name).map_err(|e| {
-            format!(
-                "Error while checking if {} exists with {} error",
-                asset_name, e
-            )
+        release: &GithubRelease,
+    ) -> Result<NymDownloadedAsset, NymReleaseError> {
+        info!("Downloading release {}...", release.tag_name);
+        let path_with_tag = self
+            .release_asset_path(asset, release)
+            .await
+            .map_err(NymReleaseError::Release)?;
+
+        let expected_checksum = self
+            .nym_github_client
+            .release_asset_checksum(release, asset)
+            .await?;
+
+        //Published checksum is known from metadata, so a verified stored asset needs no download
+        if let Some(stored_path) = NymAssetStoreUtil::verified_asset(
+            &release.tag_name,
+            asset.name(),
+            &expected_checksum,
+            &self.asset_manager.release_config().trusted_public_keys,
+        ) {
+            return Ok(NymDownloadedAsset {
+                path: stored_path,
+                expected_checksum,
+                is_verified: true,
+            });
+        }
+
+        NymAssetStoreUtil::create_release_dir(&release.tag_name)
+            .map_err(NymReleaseError::Release)?;
+        self.nym_github_client
+            .download_release_asset(release, asset, &path_with_tag)
+            .await?;
+
+        Ok(NymDownloadedAsset {
+            path: path_with_tag,
+            expected_checksum,
+            is_verified: false,
+        })
+    }
+
+    async fn verify_release(
+        &self,
+        asset: &NymReleaseAssets,
+        release: &GithubRelease,
+        downloaded: &NymDownloadedAsset,
+    ) -> Result<(), NymReleaseError> {
+        if downloaded.is_verified {
+            return Ok(());
+        }
+
+        //Never execute or install a binary that does not match its published checksum
+        let path = &downloaded.path;
+        NymChecksumUtil::verify_file(path, &downloaded.expected_checksum)
+            .map_err(NymReleaseError::Release)?;
+        let signed_by = self.verify_signature(asset, release, path).await?;
+
+        AppCmd::give_ux_permission(path).map_err(|e| {
+            NymReleaseError::Release(format!("Error while chmod {} with {} error", path, e))
         })?;
+        NymAssetStoreUtil::mark_verified(path, &downloaded.expected_checksum, signed_by.as_deref())
+            .map_err(NymReleaseError::Release)
+    }
 
-        let asset_state = match state.as_str() {
-            "active" => AssetState::Running,
-            "inactive" => AssetState::Stopped,
-            _ => {
-                return Err("Mixnode does not exist on systemd".to_string());
+    async fn verify_signature(
+        &self,
+        asset: &NymReleaseAssets,
+        release: &GithubRelease,
+        path: &str,
+    ) -> Result<Option<String>, NymReleaseError> {
+        let trusted_keys = &self.asset_manager.release_config().trusted_public_keys;
+        if trusted_keys.is_empty() {
+            warn!(
+                "No trusted public keys configured, skipping signature verification of {}",
+                path
+            );
+            return Ok(None);
+        }
+
+        let signature = match self
+            .nym_github_client
+            .release_asset_signature(release, asset)
+            .await
+        {
+            Ok(signature) => signature,
+            //Downloaded asset is left unverified in the store, it is downloaded again next time
+            Err(NymReleaseError::Transport(e)) => return Err(NymReleaseError::Transport(e)),
+            Err(NymReleaseError::Release(e)) => {
+                let quarantine_path =
+                    NymChecksumUtil::quarantine_file(path).map_err(NymReleaseError::Release)?;
+                return Err(NymReleaseError::Release(format!(
+                    "{}, signature is required since trusted keys are configured. File moved to {}",
+                    e, quarantine_path
+                )));
             }
         };
 
-        info!("Mixnode exists on sytemd with state {:?}", asset_state);
-        Ok(asset_state)
+        let signer = NymSignatureUtil::verify_file(path, &signature, trusted_keys)
+            .map_err(NymReleaseError::Release)?;
+        info!("{} is signed by trusted key {}", asset.name(), signer.name);
+        Ok(Some(signer.public_key.clone()))
     }
 
-    pub async fn stop_asset_service(&self, asset: &NymReleaseAssets) -> Result<(), String> {
-        let asset_name = asset.name();
-        info!("Stopping {}...", asset_name);
-        run_fun!(service $asset_name stop)
-            .map_err(|e| format!("Error while stopping {} with {} error", asset_name, e))?;
-        Ok(())
+    pub async fn release_asset_path(
+        &self,
+        asset: &NymReleaseAssets,
+        release: &GithubRelease,
+    ) -> Result<String, String> {
+        let path_with_tag = NymAssetStoreUtil::asset_path(&release.tag_name, asset.name());
+
+        Ok(path_with_tag)
     }
 
-    pub async fn install_latest(&self, asset: &NymReleaseAssets) -> Result<String, String> {
-        info!("Installing latest release...");
-        let download_url = self.nym_github_client.latest_release_download_url(asset)?;
-        info!("Downloading latest release from {}", download_url);
-        let path_with_latest_tag = self.latest_asset_path(asset).await?;
+    pub async fn start_update(
+        &self,
+        asset: &NymReleaseAssets,
+        release: &GithubRelease,
+        allow_downgrade: bool,
+    ) -> Result<NymUpdateResult, String> {
+        let asset_name = asset.name();
+        info!("Starting {} update to {}...", asset_name, release.tag_name);
+        let started_at = Utc::now();
+        let from_release_tag = self
+            .asset_manager
+            .asset_config(asset)
+            .ok()
+            .and_then(|asset_config| asset_config.installed.as_ref())
+            .map(|installed| installed.release_tag.clone());
+        //Nothing was changed before the transaction is written, but a failed run still belongs in
+        //the history
+        let record_failed_start = |from_version: Option<String>, e: &str| {
+            self.asset_manager
+                .append_history(&NymUpdateRecord::failed_start(
+                    asset_name,
+                    from_release_tag.clone(),
+                    from_version,
+                    &release.tag_name,
+                    started_at,
+                    e,
+                ));
+        };
 
-        run_fun!(wget -O $path_with_latest_tag $download_url)
-            .map_err(|e| format!("Error while downloading latest release with {} error", e))?;
+        let current_asset = async {
+            //Be sure that systemd daemon is reloaded to avoid any issues
+            self.asset_manager.reload_systemd_daemon().await?;
+            let current_asset_state = self.asset_manager.current_asset_state(asset).await?;
+            let current_asset_version = self.asset_manager.current_asset_version(asset).await?;
+            Ok::<_, String>((current_asset_state, current_asset_version))
+        };
+        let (current_asset_state, current_asset_version) = match current_asset.await {
+            Ok(current_asset) => current_asset,
+            Err(e) => {
+                record_failed_start(None, &e);
+                return Err(e);
+            }
+        };
 
-        AppCmd::give_ux_permission(&path_with_latest_tag).map_err(|e| {
-            format!(
-                "Error while chmod {} with {} error",
-                path_with_latest_tag, e
-            )
-        })?;
-        Ok(path_with_latest_tag)
-    }
+        //Decide from metadata first, so nothing is downloaded for the release already running
+        if self.is_release_running(asset, release).await {
+            info!(
+                "{} is already running release {}",
+                asset_name, release.tag_name
+            );
+            return Ok(NymUpdateResult::NotNecessary);
+        }
 
-    pub async fn systemd_asset_path(&self, asset: &NymReleaseAssets) -> Result<String, String> {
-        let asset_name = asset.name();
-        match asset {
-            NymReleaseAssets::MixNode => {
-                let res = run_fun!(systemctl show -p ExecStart --value $asset_name | grep -o "path=[^;]*" | cut -d= -f2).map_err(|e| format!("Error while getting mixnode systemd path with {} error", e))?;
-                info!("Mixnode systemd path is {}", res);
-                Ok(res)
+        if let AssetState::NotAvailable = current_asset_state {
+            let e = format!("{} does not exist on systemd", asset_name);
+            record_failed_start(Some(current_asset_version), &e);
+            return Ok(NymUpdateResult::Failure(e));
+        }
+
+        let downloaded = match self.download_release(asset, release).await {
+            Ok(downloaded) => downloaded,
+            Err(e) => {
+                let is_release_rejected = matches!(e, NymReleaseError::Release(_));
+                let e = String::from(e);
+                record_failed_start(Some(current_asset_version), &e);
+                self.asset_manager.record_attempt(
+                    asset,
+                    &release.tag_name,
+                    is_release_rejected,
+                    Err(&e),
+                );
+                return Err(e);
+            }
+        };
+
+        //Keep previous unit file and version to be able to roll back if anything fails after the stop
+        let systemd_manager = NymSystemdFileUtil::new(asset.clone());
+        let unit_snapshot = match systemd_manager.unit_snapshot() {
+            Ok(unit_snapshot) => unit_snapshot,
+            Err(e) => {
+                record_failed_start(Some(current_asset_version), &e);
+                return Err(e);
             }
-            NymReleaseAssets::Gateway => Err("Gateway not supported yet".to_string()),
+        };
+        let install_root = self.asset_manager.install_root(asset);
+        let mut transaction = NymUpdateTransaction {
+            asset: asset_name.to_string(),
+            from_release_tag: from_release_tag.clone(),
+            from_version: Some(current_asset_version.clone()),
+            release_tag: release.tag_name.clone(),
+            store_path: downloaded.path.clone(),
+            target_path: install_root.version_bin_path(&release.tag_name),
+            target_version: None,
+            previous_version: install_root.current_version(),
+            unit_snapshot,
+            was_running: matches!(current_asset_state, AssetState::Running),
+            phase: NymUpdatePhase::Downloaded,
+            phases: vec![NymPhaseRecord {
+                phase: NymUpdatePhase::Downloaded,
+                completed_at: Utc::now(),
+            }],
+            rollback: None,
+            started_at,
+            updated_at: Utc::now(),
+        };
+        if let Err(e) = NymUpdateStateUtil::write_transaction(asset, &transaction) {
+            self.asset_manager
+                .record_history(&transaction, Err(&e), false);
+            return Err(e);
         }
+
+        let mut is_release_rejected = false;
+        let result = match self.verify_release(asset, release, &downloaded).await {
+            Ok(()) => {
+                self.run_update_transaction(
+                    asset,
+                    release,
+                    allow_downgrade,
+                    current_asset_version,
+                    &downloaded,
+                    &mut transaction,
+                )
+                .await
+            }
+            Err(e) => {
+                is_release_rejected = matches!(e, NymReleaseError::Release(_));
+                Err(e.into())
+            }
+        };
+        let history_result = result.as_ref().map_err(|e| e.as_str());
+        self.asset_manager
+            .record_history(&transaction, history_result, false);
+        self.asset_manager.record_attempt(
+            asset,
+            &release.tag_name,
+            is_release_rejected || transaction.phase >= NymUpdatePhase::Stopped,
+            history_result,
+        );
+
+        //Every returned result is final, only a killed updater leaves its transaction behind
+        if let Err(e) = NymUpdateStateUtil::remove_transaction(asset) {
+            error!("Failed to clear {} update state: {}", asset_name, e);
+        }
+
+        result
     }
 
-    pub async fn asset_build_version(
+    //Takes the update from a verified download on, rolling back once the service was stopped
+    async fn run_update_transaction(
         &self,
         asset: &NymReleaseAssets,
-        bin_path: String,
-    ) -> Result<String, String> {
-        let res = run_fun!($bin_path --version | grep "Build Version" | awk "{print $3}").map_err(
-            |e| {
+        release: &GithubRelease,
+        allow_downgrade: bool,
+        current_asset_version: String,
+        downloaded: &NymDownloadedAsset,
+        transaction: &mut NymUpdateTransaction,
+    ) -> Result<NymUpdateResult, String> {
+        let asset_name = asset.name();
+        transaction.advance(asset, NymUpdatePhase::Verified)?;
+
+        let target_asset_version = self
+            .asset_manager
+            .asset_build_version(asset, downloaded.path.clone())
+            .await?;
+        info!(
+            "Release {} {} version is {}",
+            release.tag_name, asset_name, target_asset_version
+        );
+
+        let current_version = NymVersionUtil::parse_build_version(&current_asset_version)?;
+        let target_version = NymVersionUtil::parse_build_version(&target_asset_version)?;
+
+        if current_version == target_version {
+            //Asset may be already on target version without a record, e.g. installed manually.
+            //Unit may run the current symlink, so record the binary it resolves to
+            let systemd_asset_path = self
+                .asset_manager
+                .systemd_asset_path(asset)
+                .await?
+                .trim()
+                .to_string();
+            let current_asset_path = AppCmd::realt_path(&systemd_asset_path).map_err(|e| {
                 format!(
-                    "Error while getting {} version with {} error",
-                    asset.name(),
-                    e
+                    "Error while resolving {} path {} with {} error",
+                    asset_name, systemd_asset_path, e
                 )
-            },
-        )?;
+            })?;
+            self.asset_manager.record_installed_asset(
+                asset,
+                &release.tag_name,
+                current_asset_version,
+                current_asset_path,
+            )?;
+            return Ok(NymUpdateResult::NotNecessary);
+        }
 
-        Ok(res)
-    }
+        if target_version < current_version && !allow_downgrade {
+            warn!(
+                "Release {} ships {} {} which is older than running {}, refusing to downgrade",
+                release.tag_name, asset_name, target_version, current_version
+            );
+            return Ok(NymUpdateResult::NotNecessary);
+        }
 
-    pub async fn current_asset_version(&self, asset: &NymReleaseAssets) -> Result<String, String> {
-        let asset_name = asset.name();
-        let asset_path = self.systemd_asset_path(asset).await?.trim().to_string();
-        let res = self.asset_build_version(asset, asset_path).await?;
-        info!("Current {} version is {}", asset_name, res);
-        Ok(res)
+        transaction.target_version = Some(target_asset_version);
+        let target_asset_path = self
+            .asset_manager
+            .install_root(asset)
+            .install_version(&release.tag_name, &downloaded.path)?;
+        info!("Target {} path is {}", asset_name, target_asset_path);
+
+        if transaction.was_running {
+            self.asset_manager.stop_asset_service(asset).await?;
+        } else {
+            info!("{} is already stopped", asset_name);
+        }
+        transaction.advance(asset, NymUpdatePhase::Stopped)?;
+
+        Ok(self
+            .asset_manager
+            .complete_transaction(asset, transaction)
+            .await)
     }
 
-    pub async fn latest_asset_path(&self, asset: &NymReleaseAssets) -> Result<String, String> {
-        let asset_name = asset.name();
-        let path_with_latest_tag =
-            format!("{}-{}", self.latest_github_release.tag_name, asset_name);
+    async fn is_release_running(&self, asset: &NymReleaseAssets, release: &GithubRelease) -> bool {
+        let Some(installed) = self
+            .asset_manager
+            .asset_config(asset)
+            .ok()
+            .and_then(|asset_config| asset_config.installed.as_ref())
+        else {
+            return false;
+        };
+        if installed.release_tag != release.tag_name {
+            return false;
+        }
 
-        Ok(path_with_latest_tag)
+        //Unit runs the current symlink, so compare the binary it resolves to
+        match self.asset_manager.systemd_asset_path(asset).await {
+            Ok(current_path) => AppCmd::realt_path(current_path.trim())
+                .is_ok_and(|real_path| real_path == installed.bin_path),
+            Err(_) => false,
+        }
     }
 
-    pub async fn latest_asset_version(&self, asset: &NymReleaseAssets) -> Result<String, String> {
-        info!("Getting latest release version...");
-        let asset_name = asset.name();
-        let path = self.install_latest(asset).await?;
-        let asset_path = "./".to_string() + &path;
-        let res = self.asset_build_version(asset, asset_path).await?;
+    //Pinned assets follow their pinned release, others follow the latest release
+    fn target_release(&self, asset_config: &NymAssetUpdateConfig) -> &GithubRelease {
+        asset_config
+            .version_pin
+            .as_ref()
+            .and_then(|version_pin| {
+                self.pinned_github_releases
+                    .iter()
+                    .find(|release| &release.tag_name == version_pin)
+            })
+            .unwrap_or(&self.latest_github_release)
+    }
+
+    fn is_asset_update_needed(&self, asset_config: &NymAssetUpdateConfig) -> bool {
+        let target_tag = &self.target_release(asset_config).tag_name;
+        let Some(installed) = &asset_config.installed else {
+            info!("No installed release recorded for {}", asset_config.name);
+            return true;
+        };
 
-        info!("Latest {} version is {}", asset_name, res);
-        Ok(res)
+        info!(
+            "Current local {} release is {}, target release is {}",
+            asset_config.name, installed.release_tag, target_tag
+        );
+
+        //Pinned assets must match their pin exactly, which may also mean a downgrade
+        if asset_config.version_pin.is_some() {
+            return &installed.release_tag != target_tag;
+        }
+
+        let target_version =
+            match NymVersionUtil::parse_release_tag(target_tag, &self.release_tag_regex) {
+                Ok(version) => version,
+                Err(e) => {
+                    error!("Can not compare {} releases: {}", asset_config.name, e);
+                    return false;
+                }
+            };
+        let installed_version = match NymVersionUtil::parse_release_tag(
+            &installed.release_tag,
+            &self.release_tag_regex,
+        ) {
+            Ok(version) => version,
+            Err(e) => {
+                //Build versions are still compared before anything is changed
+                warn!("Can not compare {} releases: {}", asset_config.name, e);
+                return true;
+            }
+        };
+
+        if target_version < installed_version {
+            warn!(
+                "!!! GitHub reports {} release {} which is OLDER than installed {}, ignoring it !!!",
+                asset_config.name, target_tag, installed.release_tag
+            );
+        }
+
+        target_version > installed_version
     }
 
-    pub async fn node_id(&self) -> Result<String, String> {
-        let id = run_fun!(systemctl cat nym-mixnode | grep id | awk "{print $4}")
-            .map_err(|e| format!("Error while getting mixnode id with {} error", e))?;
+    pub async fn update_asset_if_needed(
+        &self,
+        asset: &NymReleaseAssets,
+        release: &GithubRelease,
+        allow_downgrade: bool,
+    ) -> NymUpdateResult {
+        if !NymSystemdFileUtil::new(asset.clone()).has_asset_service() {
+            return NymUpdateResult::Failure(format!(
+                "{} has no systemd service file",
+                asset.name()
+            ));
+        }
 
-        Ok(id)
+        //Failed attempts are recorded by start_update, which knows how far the update got
+        match self.start_update(asset, release, allow_downgrade).await {
+            Ok(res) => res,
+            Err(e) => NymUpdateResult::Failure(format!("Failed to start update: {}", e)),
+        }
     }
 
-    pub async fn reload_systemd_daemon(&self) -> Result<(), String> {
-        info!("Reloading systemd daemon...");
-        run_fun!(sudo systemctl daemon-reload)
-            .map_err(|e| format!("Error while reloading systemd daemon with {} error", e))?;
-        info!("Systemd daemon reloaded");
-        Ok(())
+    //Releases that crash looped or failed too often are only installed on operator request
+    fn is_release_blocked(&self, asset_config: &NymAssetUpdateConfig, release_tag: &str) -> bool {
+        asset_config.is_bad_release(release_tag)
+            || asset_config
+                .failed_attempt(release_tag)
+                .is_some_and(|attempt| {
+                    self.asset_manager
+                        .release_config()
+                        .failure_policy
+                        .is_quarantined(attempt)
+                })
     }
 
-    async fn update_systemd_file(
+    fn failed_release_skip_reason(
         &self,
-        asset: NymReleaseAssets,
-        new_exec_path: String,
-    ) -> Result<(), String> {
-        info!("Updating {} systemd file...", asset.name());
+        asset_config: &NymAssetUpdateConfig,
+    ) -> Option<NymUpdateResult> {
+        let release_tag = &self.target_release(asset_config).tag_name;
+        let failed_attempt = asset_config.failed_attempt(release_tag)?;
+        let failure_policy = &self.asset_manager.release_config().failure_policy;
 
-        let systemd_manager = NymSystemdFileUtil::new(asset.clone());
+        if failure_policy.is_quarantined(failed_attempt) {
+            return Some(NymUpdateResult::Quarantined(release_tag.clone()));
+        }
+
+        let retry_at = failure_policy.retry_at(failed_attempt);
+        (retry_at > Utc::now()).then(|| NymUpdateResult::BackingOff(release_tag.clone(), retry_at))
+    }
 
-        let bin_path = format!("{}", new_exec_path);
-        let version = self.asset_build_version(&asset, bin_path).await?;
+    //Returns the target release tag of every configured asset that has an update available
+    pub fn check_updates(&self) -> Vec<(NymReleaseAssets, Option<String>)> {
+        self.asset_manager
+            .configured_assets()
+            .into_iter()
+            .map(|(asset, asset_config)| {
+                let target_tag = &self.target_release(asset_config).tag_name;
+                let target_tag = (self.is_asset_update_needed(asset_config)
+                    && !self.is_release_blocked(asset_config, target_tag))
+                .then(|| target_tag.clone());
+                (asset, target_tag)
+            })
+            .collect()
+    }
 
-        systemd_manager
-            .update_exec_start_prop(new_exec_path.clone())
-            .await?;
+    pub async fn assets_status(&self) -> Vec<NymAssetStatus> {
+        let mut statuses = vec![];
+        for (asset, asset_config) in self.asset_manager.configured_assets() {
+            let state = self
+                .asset_manager
+                .current_asset_state(&asset)
+                .await
+                .unwrap_or(AssetState::NotAvailable);
+            let running_version = match state {
+                AssetState::NotAvailable => None,
+                _ => self.asset_manager.current_asset_version(&asset).await.ok(),
+            };
 
-        systemd_manager.update_description_prop(version).await?;
+            let update_available = self.is_asset_update_needed(asset_config);
+            let held_release_tag = (update_available && self.is_asset_update_held(asset_config))
+                .then(|| self.target_release(asset_config).tag_name.clone());
 
-        systemd_manager.systemd_reload()?;
-        Ok(())
+            let soaking_until = update_available
+                .then(|| self.release_soak_end(asset_config))
+                .flatten();
+
+            statuses.push(NymAssetStatus {
+                update_available,
+                soaking_until,
+                update_policy: asset_config.update_policy,
+                held_release_tag,
+                auto_update: asset_config.auto_update,
+                installed: asset_config.installed.clone(),
+                version_pin: asset_config.version_pin.clone(),
+                target_failed_attempt: asset_config
+                    .failed_attempt(&self.target_release(asset_config).tag_name)
+                    .map(|failed_attempt| {
+                        let failure_policy = &self.asset_manager.release_config().failure_policy;
+                        NymFailedAttemptStatus {
+                            failures: failed_attempt.failures,
+                            last_error: failed_attempt.last_error.clone(),
+                            quarantined: failure_policy.is_quarantined(failed_attempt),
+                            retry_at: failure_policy.retry_at(failed_attempt),
+                        }
+                    }),
+                unfinished_update: NymUpdateStateUtil::read_transaction(&asset).unwrap_or_else(
+                    |e| {
+                        error!("{}", e);
+                        None
+                    },
+                ),
+                bad_release_tags: asset_config
+                    .bad_releases
+                    .iter()
+                    .map(|bad_release| bad_release.release_tag.clone())
+                    .collect(),
+                latest_release_tag: self.latest_github_release.tag_name.clone(),
+                target_release_tag: self.target_release(asset_config).tag_name.clone(),
+                asset,
+                state,
+                running_version,
+            });
+        }
+
+        statuses
     }
 
-    pub async fn latest_target_asset_path(
-        &self,
-        asset: &NymReleaseAssets,
-    ) -> Result<String, String> {
-        let path_with_latest_tag = self.latest_asset_path(asset).await?;
-        let latest_target_asset_path = AppCmd::realt_path(&path_with_latest_tag).map_err(|e| {
-            format!(
-                "Error while getting real path of latest assets with {} error",
-                e
-            )
-        })?;
+    //Returns when the target release of an unpinned asset may be adopted, if that is still in the future
+    fn release_soak_end(&self, asset_config: &NymAssetUpdateConfig) -> Option<DateTime<Utc>> {
+        let min_release_age_hours = self.asset_manager.release_config().min_release_age_hours;
+        if min_release_age_hours == 0 || asset_config.version_pin.is_some() {
+            return None;
+        }
+
+        let release = self.target_release(asset_config);
+        let published_at = NymGithubClient::release_published_at(release)?;
+
+        let soak_end = published_at + Duration::hours(min_release_age_hours as i64);
+        if soak_end <= Utc::now() {
+            return None;
+        }
 
         info!(
-            "Latest target {} path is {}",
-            asset.name(),
-            latest_target_asset_path
+            "Release {} was published at {}, {} will adopt it after {}",
+            release.tag_name, published_at, asset_config.name, soak_end
         );
+        Some(soak_end)
+    }
+
+    //Pinned assets and assets without an installed record are not held back by the policy
+    fn is_asset_update_held(&self, asset_config: &NymAssetUpdateConfig) -> bool {
+        if asset_config.version_pin.is_some() {
+            return false;
+        }
+
+        let policy = asset_config.update_policy;
+        let Some(installed) = &asset_config.installed else {
+            return policy == NymUpdatePolicy::Manual;
+        };
+
+        let target_tag = &self.target_release(asset_config).tag_name;
+        let versions =
+            NymVersionUtil::parse_release_tag(&installed.release_tag, &self.release_tag_regex)
+                .and_then(|installed| {
+                    Ok((
+                        installed,
+                        NymVersionUtil::parse_release_tag(target_tag, &self.release_tag_regex)?,
+                    ))
+                });
 
-        Ok(latest_target_asset_path)
+        let is_held = match versions {
+            Ok((installed_version, target_version)) => {
+                !policy.allows(&installed_version, &target_version)
+            }
+            Err(_) => policy != NymUpdatePolicy::Major,
+        };
+
+        if is_held {
+            info!(
+                "{} release {} is held back by {} update policy",
+                asset_config.name,
+                target_tag,
+                policy.as_str()
+            );
+        }
+
+        is_held
     }
 
-    pub async fn start_asset_service(&self, asset: &NymReleaseAssets) -> Result<(), String> {
-        let asset_name = asset.name();
-        info!("Starting {}...", asset_name);
-        run_fun!(systemctl start $asset_name)
-            .map_err(|e| format!("Error while restarting {} with {} error", asset_name, e))?;
-        let latest_asset_path = self.latest_target_asset_path(asset).await?;
+    //Reason `update --asset` skips the asset. It bypasses auto update, soak time, update policy and
+    //backoff, but not releases blocked until `retry`
+    fn explicit_update_skip_reason(
+        &self,
+        asset_config: &NymAssetUpdateConfig,
+    ) -> Option<NymUpdateResult> {
+        let release_tag = &self.target_release(asset_config).tag_name;
+        if !self.is_asset_update_needed(asset_config) {
+            Some(NymUpdateResult::NotNecessary)
+        } else if asset_config.is_bad_release(release_tag) {
+            Some(NymUpdateResult::BadRelease(release_tag.clone()))
+        } else if self.is_release_blocked(asset_config, release_tag) {
+            Some(NymUpdateResult::Quarantined(release_tag.clone()))
+        } else {
+            None
+        }
+    }
 
-        info!(
-            "Successfully {} started release {}",
-            asset_name, latest_asset_path
-        );
-        Ok(())
+    pub async fn update_asset(&self, asset: &NymReleaseAssets) -> Result<NymUpdateResult, String> {
+        let asset_config = self.asset_manager.asset_config(asset)?;
+        if let Some(skip_reason) = self.explicit_update_skip_reason(asset_config) {
+            return Ok(skip_reason);
+        }
+
+        let release = self.target_release(asset_config);
+        let allow_downgrade = asset_config.version_pin.is_some();
+        Ok(self
+            .update_asset_if_needed(asset, release, allow_downgrade)
+            .await)
     }
 
-    pub async fn init_mixnode_node_with_path(&self, path: String) -> Result<(), String> {
-        info!("Initing node...");
-        let id = self.node_id().await?;
+    //Installs the exact release with the given tag, which may also be a downgrade
+    pub async fn install_asset_release(
+        &self,
+        asset: &NymReleaseAssets,
+        tag: &str,
+    ) -> Result<NymUpdateResult, String> {
+        let asset_config = self.asset_manager.asset_config(asset)?;
 
-        let ip = run_fun!(curl ipinfo.io | jq -r ".ip")
-            .map_err(|e| format!("Error while getting ip address with {} error", e))?;
-        info!("Initing node with id {} and ip {}", id, ip);
-        let res = run_fun!(sudo $path init --id $id --host $ip)
-            .map_err(|e| format!("Error while initing mixnode with {} error", e))?;
-        info!("Init result: {}", res);
-        Ok(())
+        let channel = self.asset_manager.release_config().release_channel;
+        let release = self
+            .nym_github_client
+            .nym_release_by_tag(tag, &channel)
+            .await?;
+        info!("Installing {} release {}", asset.name(), release.tag_name);
+
+        //Operator asked for this exact release, so a bad record only warns
+        if asset_config.is_bad_release(&release.tag_name) {
+            warn!(
+                "{} release {} is recorded as bad, installing it anyway",
+                asset.name(),
+                release.tag_name
+            );
+        }
+
+        Ok(self.update_asset_if_needed(asset, &release, true).await)
     }
 
-    pub async fn start_update(&self) -> Result<NymUpdateResult, String> {
-        info!("Starting update...");
-        //Be sure that systemd daemon is reloaded to avoid any issues
-        self.reload_systemd_daemon().await?;
+    //Reason an automatic update of the asset is skipped, None when it should be applied
+    fn auto_update_skip_reason(
+        &self,
+        asset_config: &NymAssetUpdateConfig,
+    ) -> Option<NymUpdateResult> {
+        let release = self.target_release(asset_config);
+        if !asset_config.auto_update {
+            Some(NymUpdateResult::Disabled)
+        } else if !self.is_asset_update_needed(asset_config) {
+            Some(NymUpdateResult::NotNecessary)
+        } else if asset_config.is_bad_release(&release.tag_name) {
+            Some(NymUpdateResult::BadRelease(release.tag_name.clone()))
+        } else if let Some(skip_reason) = self.failed_release_skip_reason(asset_config) {
+            Some(skip_reason)
+        } else if let Some(soak_end) = self.release_soak_end(asset_config) {
+            Some(NymUpdateResult::Soaking(release.tag_name.clone(), soak_end))
+        } else if self.is_asset_update_held(asset_config) {
+            Some(NymUpdateResult::Held(release.tag_name.clone()))
+        } else {
+            None
+        }
+    }
 
-        let temp_defined_asset = NymReleaseAssets::MixNode;
+    pub async fn update_if_needed(&self) -> Vec<(NymReleaseAssets, NymUpdateResult)> {
+        info!("Checking for updates...");
+        info!("Latest release is {}", self.latest_github_release.tag_name);
 
-        let current_asset_state = self.current_asset_state(&temp_defined_asset).await?;
-        let current_asset_version = self.current_asset_version(&temp_defined_asset).await?;
-        let latest_asset_version = self.latest_asset_version(&temp_defined_asset).await?;
-        let latest_target_asset_path = self.latest_target_asset_path(&temp_defined_asset).await?;
+        let mut results = vec![];
+        for (asset, asset_config) in self.asset_manager.configured_assets() {
+            let asset_result = match self.auto_update_skip_reason(asset_config) {
+                Some(skip_reason) => skip_reason,
+                None => {
+                    let release = self.target_release(asset_config);
+                    let allow_downgrade = asset_config.version_pin.is_some();
+                    self.update_asset_if_needed(&asset, release, allow_downgrade)
+                        .await
+                }
+            };
 
-        if current_asset_version == latest_asset_version {
-            return Ok(NymUpdateResult::NotNecessary);
+            results.push((asset, asset_result));
         }
 
-        match current_asset_state {
-            AssetState::Running => {
-                self.stop_asset_service(&temp_defined_asset).await?;
-            }
-            AssetState::Stopped => {
-                info!("Mixnode is already stopped");
+        results
+    }
+
+    //Plans what `update` would do without changing anything, for every asset or explicitly for one
+    pub async fn plan_updates(
+        &self,
+        asset: Option<&NymReleaseAssets>,
+    ) -> Result<Vec<NymUpdatePlan>, String> {
+        let mut plans = vec![];
+        match asset {
+            Some(asset) => {
+                let asset_config = self.asset_manager.asset_config(asset)?;
+                let skip_reason = self.explicit_update_skip_reason(asset_config);
+                plans.push(
+                    self.plan_asset_update(asset, asset_config, skip_reason)
+                        .await,
+                );
             }
-            AssetState::NotAvailable => {
-                return Ok(NymUpdateResult::Failure(
-                    "Mixnode does not exist on systemd".to_string(),
-                ))
+            None => {
+                for (asset, asset_config) in self.asset_manager.configured_assets() {
+                    let skip_reason = self.auto_update_skip_reason(asset_config);
+                    plans.push(
+                        self.plan_asset_update(&asset, asset_config, skip_reason)
+                            .await,
+                    );
+                }
             }
         }
 
-        self.init_mixnode_node_with_path(latest_target_asset_path.clone())
-            .await?;
-        self.update_systemd_file(temp_defined_asset.clone(), latest_target_asset_path)
-            .await?;
-        self.start_asset_service(&temp_defined_asset).await?;
+        Ok(plans)
+    }
 
-        NymConfigFileUtil::update_release_tag(self.latest_github_release.tag_name.clone())
-            .map_err(|e| format!("Error while updating release tag with {} error", e))?;
+    async fn plan_asset_update(
+        &self,
+        asset: &NymReleaseAssets,
+        asset_config: &NymAssetUpdateConfig,
+        skip_reason: Option<NymUpdateResult>,
+    ) -> NymUpdatePlan {
+        let release = self.target_release(asset_config);
+        let mut plan = NymUpdatePlan {
+            asset: asset.clone(),
+            release_tag: release.tag_name.clone(),
+            facts: vec![],
+            actions: vec![],
+            result: NymUpdateResult::Success,
+        };
+
+        if let Some(skip_reason) = skip_reason {
+            plan.result = skip_reason;
+            return plan;
+        }
+
+        let allow_downgrade = asset_config.version_pin.is_some();
+        if let Err(e) = self
+            .plan_start_update(asset, release, allow_downgrade, &mut plan)
+            .await
+        {
+            plan.result = NymUpdateResult::Failure(e);
+        }
 
-        Ok(NymUpdateResult::Success)
+        plan
     }
 
-    pub async fn update_if_needed(&self) -> NymUpdateResult {
-        info!("Checking for updates...");
-        info!("Latest release is {}", self.latest_github_release.tag_name);
-        info!(
-            "Current local release is {}",
-            self.local_release_config.release_tag
-        );
+    //Mirrors start_update step by step, only running read only discovery commands
+    async fn plan_start_update(
+        &self,
+        asset: &NymReleaseAssets,
+        release: &GithubRelease,
+        allow_downgrade: bool,
+        plan: &mut NymUpdatePlan,
+    ) -> Result<(), String> {
+        let asset_name = asset.name();
+        let systemd_manager = NymSystemdFileUtil::new(asset.clone());
+        if !systemd_manager.has_asset_service() {
+            plan.result =
+                NymUpdateResult::Failure(format!("{} has no systemd service file", asset_name));
+            return Ok(());
+        }
 
-        let is_update_needed =
-            self.latest_github_release.tag_name != self.local_release_config.release_tag;
+        let current_asset_state = self.asset_manager.current_asset_state(asset).await?;
+        let current_asset_version = self.asset_manager.current_asset_version(asset).await?;
+        plan.add_fact("systemd state", format!("{:?}", current_asset_state));
+        plan.add_fact("running version", current_asset_version.clone());
+        plan.add_fact("ExecStart", systemd_manager.exec_start_full_line().await?);
 
-        if !is_update_needed {
-            return NymUpdateResult::NotNecessary;
+        if let Ok(Some(transaction)) = NymUpdateStateUtil::read_transaction(asset) {
+            //Transaction of a running updater is still being applied, not left behind
+            let (name, next_step) = if NymUpdateStateUtil::is_locked() {
+                ("update in progress", "by another updater")
+            } else {
+                ("unfinished update", "settled before updating")
+            };
+            plan.add_fact(
+                name,
+                format!(
+                    "{} at phase {}, {}",
+                    transaction.release_tag,
+                    transaction.phase.as_str(),
+                    next_step
+                ),
+            );
         }
 
-        match self.start_update().await {
-            Ok(res) => res,
-            Err(e) => NymUpdateResult::Failure(format!("Failed to start update: {}", e)),
+        if self.is_release_running(asset, release).await {
+            plan.result = NymUpdateResult::NotNecessary;
+            return Ok(());
+        }
+
+        if let AssetState::NotAvailable = current_asset_state {
+            plan.result =
+                NymUpdateResult::Failure(format!("{} does not exist on systemd", asset_name));
+            return Ok(());
+        }
+
+        plan.add_action("Reload systemd daemon".to_string());
+
+        let expected_checksum = self
+            .nym_github_client
+            .release_asset_checksum(release, asset)
+            .await?;
+        let store_path = self.release_asset_path(asset, release).await?;
+        plan.add_action(format!(
+            "Snapshot systemd unit file for rollback into {} update state",
+            asset_name
+        ));
+        let target_asset_version = match NymAssetStoreUtil::verified_asset(
+            &release.tag_name,
+            asset_name,
+            &expected_checksum,
+            &self.asset_manager.release_config().trusted_public_keys,
+        ) {
+            Some(stored_path) => {
+                plan.add_action(format!("Reuse verified {} from store", stored_path));
+                Some(
+                    self.asset_manager
+                        .asset_build_version(asset, stored_path)
+                        .await?,
+                )
+            }
+            None => {
+                let release_asset = NymGithubClient::release_asset(release, asset_name)?;
+                plan.add_action(format!(
+                    "Download {} to {} and verify SHA-256 {}",
+                    release_asset.browser_download_url, store_path, expected_checksum
+                ));
+
+                let trusted_keys = &self.asset_manager.release_config().trusted_public_keys;
+                if !trusted_keys.is_empty() {
+                    plan.add_action(format!(
+                        "Verify {}.minisig signature against {} trusted keys",
+                        asset_name,
+                        trusted_keys.len()
+                    ));
+                }
+                None
+            }
+        };
+
+        match &target_asset_version {
+            Some(target_asset_version) => {
+                plan.add_fact("target version", target_asset_version.clone());
+                let current_version = NymVersionUtil::parse_build_version(&current_asset_version)?;
+                let target_version = NymVersionUtil::parse_build_version(target_asset_version)?;
+
+                if current_version == target_version {
+                    plan.add_action(format!(
+                        "Record {} as installed on release {} in config, it already runs that version",
+                        asset_name, release.tag_name
+                    ));
+                    plan.result = NymUpdateResult::NotNecessary;
+                    return Ok(());
+                }
+
+                if target_version < current_version && !allow_downgrade {
+                    plan.add_fact(
+                        "downgrade",
+                        "refused, target is older than running".to_string(),
+                    );
+                    plan.result = NymUpdateResult::NotNecessary;
+                    return Ok(());
+                }
+            }
+            None => {
+                plan.add_fact("target version", "known after download".to_string());
+                plan.add_action(format!(
+                    "Compare downloaded build version with running {}, stop if equal{}",
+                    current_asset_version,
+                    if allow_downgrade { "" } else { " or older" }
+                ));
+            }
         }
+
+        let install_root = self.asset_manager.install_root(asset);
+        let target_asset_path = install_root.version_bin_path(&release.tag_name);
+        plan.add_action(format!("Install {} to {}", store_path, target_asset_path));
+
+        if let AssetState::Running = current_asset_state {
+            plan.add_action(format!("Stop {}", asset_name));
+        }
+
+        let init_args = self.asset_manager.init_args(asset).await?;
+        plan.add_action(format!(
+            "Run sudo {} init {}",
+            target_asset_path,
+            init_args.join(" ")
+        ));
+        plan.add_action(format!(
+            "Point {} at {}",
+            install_root.current_link_path(),
+            release.tag_name
+        ));
+
+        let exec_start = systemd_manager
+            .exec_start_line_with_path(&install_root.current_bin_path())
+            .await?;
+        let description = systemd_manager.description(
+            target_asset_version
+                .as_deref()
+                .unwrap_or("<downloaded build version>"),
+        );
+        plan.add_action(format!("Set ExecStart={}", exec_start));
+        plan.add_action(format!("Set Description={}", description));
+        plan.add_action("Reload systemd daemon".to_string());
+        plan.add_action(format!("Start {}", asset_name));
+
+        let health_check_config = &self.asset_manager.release_config().health_check;
+        if health_check_config.stable_secs > 0 {
+            plan.add_action(format!(
+                "Verify {} stays active for {} seconds without restarts",
+                asset_name, health_check_config.stable_secs
+            ));
+        }
+        if let Some(health_url) = self
+            .asset_manager
+            .asset_config(asset)
+            .ok()
+            .and_then(|asset_config| asset_config.health_url.as_ref())
+        {
+            plan.add_action(format!(
+                "Verify {} answers within {} seconds",
+                health_url, health_check_config.http_timeout_secs
+            ));
+        }
+        plan.add_action(format!(
+            "Record {} as installed on release {} in config",
+            asset_name, release.tag_name
+        ));
+        plan.add_action(format!(
+            "Prune {} versions beyond the newest {}",
+            asset_name,
+            self.asset_manager.release_config().install.keep_versions
+        ));
+
+        Ok(())
     }
 }
 
+//Ordered actions an update would take, with the discovered facts they are based on
 #[derive(Debug)]
+pub struct NymUpdatePlan {
+    pub asset: NymReleaseAssets,
+    pub release_tag: String,
+    pub facts: Vec<(String, String)>,
+    pub actions: Vec<String>,
+    //Result the update is expected to end with if every action succeeds
+    pub result: NymUpdateResult,
+}
+
+impl NymUpdatePlan {
+    fn add_fact(&mut self, name: &str, value: String) {
+        self.facts.push((name.to_string(), value));
+    }
+
+    fn add_action(&mut self, action: String) {
+        self.actions.push(action);
+    }
+}
+
+//Release asset in the store, verified already when it was reused from an earlier download
+struct NymDownloadedAsset {
+    path: String,
+    expected_checksum: String,
+    is_verified: bool,
+}
+
+#[derive(Debug, Clone)]
 pub enum AssetState {
     Running,
     Stopped,
     NotAvailable,
 }
 
+#[derive(Debug)]
 pub enum NymUpdateResult {
     Success,
     NotNecessary,
+    Disabled,
+    Held(String),
+    Soaking(String, DateTime<Utc>),
+    BadRelease(String),
+    Quarantined(String),
+    BackingOff(String, DateTime<Utc>),
+    RolledBack(String),
     Failure(String),
 }
+
+#[derive(Debug)]
+pub struct NymAssetStatus {
+    pub asset: NymReleaseAssets,
+    pub auto_update: bool,
+    pub update_policy: NymUpdatePolicy,
+    pub held_release_tag: Option<String>,
+    pub soaking_until: Option<DateTime<Utc>>,
+    pub state: AssetState,
+    pub running_version: Option<String>,
+    pub installed: Option<NymInstalledAsset>,
+    pub version_pin: Option<String>,
+    pub bad_release_tags: Vec<String>,
+    pub unfinished_update: Option<NymUpdateTransaction>,
+    pub target_failed_attempt: Option<NymFailedAttemptStatus>,
+    pub latest_release_tag: String,
+    pub target_release_tag: String,
+    pub update_available: bool,
+}
+
+#[derive(Debug)]
+pub struct NymFailedAttemptStatus {
+    pub failures: u32,
+    pub last_error: String,
+    pub quarantined: bool,
+    pub retry_at: DateTime<Utc>,
+}
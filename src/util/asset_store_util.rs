@@ -0,0 +1,120 @@
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::{NymChecksumUtil, NymTrustedPublicKey};
+
+const ASSET_STORE_DIR: &str = "./store";
+
+//Downloaded assets are kept under `<store>/<release tag>/<asset>`, next to a `.sha256` marker
+//that is only written once the asset passed checksum and signature verification. The marker
+//records the key that signed the asset, so trusted keys added later still apply to stored assets
+pub struct NymAssetStoreUtil {}
+
+impl NymAssetStoreUtil {
+    pub fn asset_path(release_tag: &str, asset_name: &str) -> String {
+        format!("{}/{}/{}", ASSET_STORE_DIR, release_tag, asset_name)
+    }
+
+    pub fn create_release_dir(release_tag: &str) -> Result<(), String> {
+        let release_dir = format!("{}/{}", ASSET_STORE_DIR, release_tag);
+        fs::create_dir_all(&release_dir).map_err(|e| {
+            format!(
+                "Error while creating store dir {} with {} error",
+                release_dir, e
+            )
+        })
+    }
+
+    //Returns the stored asset only if it was verified before, was signed by a key that is still
+    //trusted when keys are configured, and its content still matches
+    pub fn verified_asset(
+        release_tag: &str,
+        asset_name: &str,
+        expected_sha256: &str,
+        trusted_keys: &[NymTrustedPublicKey],
+    ) -> Option<String> {
+        let path = Self::asset_path(release_tag, asset_name);
+        let marker_file = fs::read_to_string(Self::verified_marker_path(&path)).ok()?;
+        let Ok(marker) = serde_json::from_str::<NymVerifiedMarker>(&marker_file) else {
+            info!("Stored {} has no valid marker, downloading again", path);
+            return None;
+        };
+
+        let expected_sha256 = expected_sha256.to_lowercase();
+        if marker.sha256 != expected_sha256 {
+            info!(
+                "Stored {} does not match published checksum anymore, downloading again",
+                path
+            );
+            return None;
+        }
+
+        let is_signed_by_trusted_key = marker.signed_by.as_ref().is_some_and(|signed_by| {
+            trusted_keys
+                .iter()
+                .any(|trusted_key| &trusted_key.public_key == signed_by)
+        });
+        if !trusted_keys.is_empty() && !is_signed_by_trusted_key {
+            info!(
+                "Stored {} was not signed by a currently trusted key, downloading again",
+                path
+            );
+            return None;
+        }
+
+        match NymChecksumUtil::file_sha256(&path) {
+            Ok(actual_sha256) if actual_sha256 == expected_sha256 => {
+                info!("Reusing verified {} from store", path);
+                Some(path)
+            }
+            Ok(actual_sha256) => {
+                warn!(
+                    "Stored {} changed on disk, expected {} but got {}, downloading again",
+                    path, expected_sha256, actual_sha256
+                );
+                let _ = fs::remove_file(Self::verified_marker_path(&path));
+                None
+            }
+            Err(e) => {
+                warn!("{}, downloading again", e);
+                None
+            }
+        }
+    }
+
+    //signed_by is the public key that verified the signature, None when no keys were configured
+    pub fn mark_verified(path: &str, sha256: &str, signed_by: Option<&str>) -> Result<(), String> {
+        let marker_path = Self::verified_marker_path(path);
+        let marker = NymVerifiedMarker {
+            sha256: sha256.to_lowercase(),
+            signed_by: signed_by.map(|signed_by| signed_by.to_string()),
+        };
+        let marker_file = serde_json::to_string(&marker)
+            .map_err(|e| format!("Error while serializing store marker with {} error", e))?;
+        fs::write(&marker_path, marker_file)
+            .map_err(|e| format!("Error while writing file {} with {} error", marker_path, e))
+    }
+
+    pub fn remove_asset(release_tag: &str, asset_name: &str) {
+        let path = Self::asset_path(release_tag, asset_name);
+        let _ = fs::remove_file(Self::verified_marker_path(&path));
+        if fs::remove_file(&path).is_ok() {
+            info!("Removed {} from store", path);
+        }
+
+        //Release dir is only removed once no other asset of the release is stored
+        let _ = fs::remove_dir(format!("{}/{}", ASSET_STORE_DIR, release_tag));
+    }
+
+    fn verified_marker_path(path: &str) -> String {
+        format!("{}.sha256", path)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NymVerifiedMarker {
+    sha256: String,
+    signed_by: Option<String>,
+}
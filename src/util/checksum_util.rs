@@ -0,0 +1,210 @@
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::Path,
+};
+
+use sha2::{Digest, Sha256};
+use tracing::{error, info};
+
+const QUARANTINE_DIR: &str = "./quarantine";
+
+pub struct NymChecksumUtil {}
+
+impl NymChecksumUtil {
+    pub fn file_sha256(path: &str) -> Result<String, String> {
+        let mut file = File::open(path)
+            .map_err(|e| format!("Error while opening {} with {} error", path, e))?;
+
+        let mut hasher = Sha256::new();
+        let mut buffer = [0u8; 64 * 1024];
+        loop {
+            let read = file
+                .read(&mut buffer)
+                .map_err(|e| format!("Error while reading {} with {} error", path, e))?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+
+        Ok(hex::encode(hasher.finalize()))
+    }
+
+    //Finds the checksum of `asset_name` in a `sha256sum` style file. A line holding only a checksum is
+    //accepted when `is_asset_checksum_file`, i.e. the file is `<asset>.sha256`, since a bare checksum
+    //in a file listing several assets says nothing about which asset it belongs to
+    pub fn find_in_checksums_file(
+        content: &str,
+        asset_name: &str,
+        is_asset_checksum_file: bool,
+    ) -> Option<String> {
+        content.lines().find_map(|line| {
+            let mut parts = line.split_whitespace();
+            let checksum = parts.next()?;
+            match parts.next() {
+                //sha256sum marks binary mode files with a leading '*'
+                Some(file_name) if file_name.trim_start_matches('*') == asset_name => {
+                    Some(checksum.to_lowercase())
+                }
+                None if is_asset_checksum_file && Self::is_sha256_hex(checksum) => {
+                    Some(checksum.to_lowercase())
+                }
+                _ => None,
+            }
+        })
+    }
+
+    //Finds the checksum of `asset_name` in a nym release `hashes.json` file
+    pub fn find_in_hashes_json(content: &str, asset_name: &str) -> Option<String> {
+        let hashes = serde_json::from_str::<serde_json::Value>(content).ok()?;
+        hashes
+            .get("assets")?
+            .get(asset_name)?
+            .get("sha256")?
+            .as_str()
+            .map(|checksum| checksum.to_lowercase())
+    }
+
+    pub fn is_sha256_hex(value: &str) -> bool {
+        value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())
+    }
+
+    pub fn verify_file(path: &str, expected_sha256: &str) -> Result<(), String> {
+        let actual_sha256 = Self::file_sha256(path)?;
+        if actual_sha256 != expected_sha256.to_lowercase() {
+            let quarantine_path = Self::quarantine_file(path)?;
+            let err = format!(
+                "Checksum mismatch for {}, expected {} but got {}. File moved to {}",
+                path, expected_sha256, actual_sha256, quarantine_path
+            );
+            error!(err);
+            return Err(err);
+        }
+
+        info!("Checksum verified for {} ({})", path, actual_sha256);
+        Ok(())
+    }
+
+    //Keeps one copy per release and content under `<quarantine>/<release tag>/<asset>-<sha256>`, a
+    //file that is already kept there is only removed, so a rejected release retried later adds nothing
+    pub fn quarantine_file(path: &str) -> Result<String, String> {
+        let file_path = Path::new(path);
+        let file_name = file_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("asset");
+        let release_tag = file_path
+            .parent()
+            .and_then(|parent| parent.file_name())
+            .and_then(|name| name.to_str())
+            .unwrap_or("release");
+
+        let quarantine_dir = format!("{}/{}", QUARANTINE_DIR, release_tag);
+        fs::create_dir_all(&quarantine_dir).map_err(|e| {
+            format!(
+                "Error while creating quarantine dir {} with {} error",
+                quarantine_dir, e
+            )
+        })?;
+
+        let quarantine_path = format!(
+            "{}/{}-{}",
+            quarantine_dir,
+            file_name,
+            Self::file_sha256(path)?
+        );
+        if Path::new(&quarantine_path).exists() {
+            info!("{} is already quarantined as {}", path, quarantine_path);
+            fs::remove_file(path)
+                .map_err(|e| format!("Error while removing {} with {} error", path, e))?;
+            return Ok(quarantine_path);
+        }
+
+        fs::rename(path, &quarantine_path)
+            .map_err(|e| format!("Error while moving {} to quarantine with {} error", path, e))?;
+
+        Ok(quarantine_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIXNODE_SHA256: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+    const GATEWAY_SHA256: &str = "60303ae22b998861bce3b28f33eec1be758a213c86c93c076dbe9f558c11c752";
+
+    #[test]
+    fn finds_asset_in_checksums_file() {
+        let content = format!(
+            "{}  nym-gateway\n{}  nym-mixnode\n",
+            GATEWAY_SHA256, MIXNODE_SHA256
+        );
+        assert_eq!(
+            NymChecksumUtil::find_in_checksums_file(&content, "nym-mixnode", false).as_deref(),
+            Some(MIXNODE_SHA256)
+        );
+        assert_eq!(
+            NymChecksumUtil::find_in_checksums_file(&content, "nym-client", false),
+            None
+        );
+    }
+
+    #[test]
+    fn accepts_binary_mode_marker_and_uppercase() {
+        let content = format!("{} *nym-mixnode", MIXNODE_SHA256.to_uppercase());
+        assert_eq!(
+            NymChecksumUtil::find_in_checksums_file(&content, "nym-mixnode", false).as_deref(),
+            Some(MIXNODE_SHA256)
+        );
+    }
+
+    #[test]
+    fn accepts_single_checksum_file() {
+        //`<asset>.sha256` files may contain the bare checksum only
+        let content = format!("{}\n", MIXNODE_SHA256);
+        assert_eq!(
+            NymChecksumUtil::find_in_checksums_file(&content, "nym-mixnode", true).as_deref(),
+            Some(MIXNODE_SHA256)
+        );
+        assert_eq!(
+            NymChecksumUtil::find_in_checksums_file("not-a-checksum\n", "nym-mixnode", true),
+            None
+        );
+    }
+
+    #[test]
+    fn ignores_bare_checksum_in_shared_checksums_file() {
+        let content = format!("{}\n{}  nym-mixnode\n", GATEWAY_SHA256, MIXNODE_SHA256);
+        assert_eq!(
+            NymChecksumUtil::find_in_checksums_file(&content, "nym-mixnode", false).as_deref(),
+            Some(MIXNODE_SHA256)
+        );
+        assert_eq!(
+            NymChecksumUtil::find_in_checksums_file(&content, "nym-gateway", false),
+            None
+        );
+    }
+
+    #[test]
+    fn finds_asset_in_hashes_json() {
+        let content = format!(
+            r#"{{"assets": {{"nym-mixnode": {{"sha256": "{}"}}, "nym-gateway": {{"sha256": "{}"}}}}}}"#,
+            MIXNODE_SHA256.to_uppercase(),
+            GATEWAY_SHA256
+        );
+        assert_eq!(
+            NymChecksumUtil::find_in_hashes_json(&content, "nym-mixnode").as_deref(),
+            Some(MIXNODE_SHA256)
+        );
+        assert_eq!(
+            NymChecksumUtil::find_in_hashes_json(&content, "nym-client"),
+            None
+        );
+        assert_eq!(
+            NymChecksumUtil::find_in_hashes_json("not json", "nym-mixnode"),
+            None
+        );
+    }
+}
@@ -1,8 +1,13 @@
 use std::fs;
 
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+use semver::Version;
 use serde::{Deserialize, Serialize};
 use tracing::error;
 
+use crate::{appclient::GithubRelease, constants::NymReleaseAssets};
+
 pub struct NymConfigFileUtil {}
 
 const NYM_CONFIG_FILE_NAME: &str = "auto_update_config.json";
@@ -20,23 +25,49 @@ impl NymConfigFileUtil {
         let current_config =
             serde_json::from_str::<NymReleaseConfig>(&config_file).map_err(|e| e.to_string())?;
 
+        current_config.upstream.tag_regex().map_err(|e| {
+            let err = format!("Invalid config file {}: {}", NYM_CONFIG_FILE_NAME, e);
+            error!(err);
+            err
+        })?;
+
+        for asset_config in &current_config.assets {
+            asset_config.asset().map_err(|e| {
+                let err = format!("Invalid config file {}: {}", NYM_CONFIG_FILE_NAME, e);
+                error!(err);
+                err
+            })?;
+        }
+
         Ok(current_config)
     }
 
     pub fn _write_config_file(config: &NymReleaseConfig) -> Result<(), String> {
         let config_file = serde_json::to_string_pretty(&config).map_err(|e| {
-            let err = format!(
-                "Error while serializing config file with {} error",
-                e.to_string()
-            );
+            let err = format!("Error while serializing config file with {} error", e);
             error!(err);
             err
         })?;
 
-        fs::write(NYM_CONFIG_FILE_NAME, config_file).map_err(|e| {
+        //Config is rewritten during updates, so it is renamed over the previous one and a crash never
+        //leaves a truncated config that would keep recovery from running
+        let tmp_path = format!("{}.tmp", NYM_CONFIG_FILE_NAME);
+        fs::write(&tmp_path, config_file).map_err(|e| {
+            let err = format!("Error while writing file {} with {} error", tmp_path, e);
+
+            error!(err);
+            err
+        })?;
+
+        //New file keeps the permissions the operator gave the previous one
+        if let Ok(metadata) = fs::metadata(NYM_CONFIG_FILE_NAME) {
+            let _ = fs::set_permissions(&tmp_path, metadata.permissions());
+        }
+
+        fs::rename(&tmp_path, NYM_CONFIG_FILE_NAME).map_err(|e| {
             let err = format!(
-                "Error while writing file {} with {} error",
-                NYM_CONFIG_FILE_NAME, e
+                "Error while moving {} to {} with {} error",
+                tmp_path, NYM_CONFIG_FILE_NAME, e
             );
 
             error!(err);
@@ -46,21 +77,461 @@ impl NymConfigFileUtil {
         Ok(())
     }
 
-    pub fn update_release_tag(new_tag: String) -> Result<(), String> {
+    pub fn update_installed_asset(
+        asset: &NymReleaseAssets,
+        installed: NymInstalledAsset,
+    ) -> Result<(), String> {
+        Self::update_asset_config(asset, |asset_config| {
+            asset_config.installed = Some(installed);
+        })
+    }
+
+    pub fn add_bad_release(
+        asset: &NymReleaseAssets,
+        bad_release: NymBadRelease,
+    ) -> Result<(), String> {
+        Self::update_asset_config(asset, |asset_config| {
+            asset_config
+                .bad_releases
+                .retain(|recorded| recorded.release_tag != bad_release.release_tag);
+            asset_config.bad_releases.push(bad_release);
+        })
+    }
+
+    //Counts a failed update to release_tag and returns the updated record
+    pub fn add_failed_attempt(
+        asset: &NymReleaseAssets,
+        release_tag: &str,
+        error: &str,
+    ) -> Result<NymFailedAttempt, String> {
+        Self::update_asset_config(asset, |asset_config| {
+            let failures = asset_config
+                .failed_attempt(release_tag)
+                .map(|attempt| attempt.failures)
+                .unwrap_or(0);
+            asset_config
+                .failed_attempts
+                .retain(|attempt| attempt.release_tag != release_tag);
+
+            let failed_attempt = NymFailedAttempt {
+                release_tag: release_tag.to_string(),
+                failures: failures + 1,
+                last_error: error.to_string(),
+                last_failed_at: Utc::now(),
+            };
+            asset_config.failed_attempts.push(failed_attempt.clone());
+            failed_attempt
+        })
+    }
+
+    //Removes failed attempts and bad release records of the asset, for one release or all of them.
+    //Returns how many records were removed
+    pub fn clear_failed_releases(
+        asset: &NymReleaseAssets,
+        release_tag: Option<&str>,
+    ) -> Result<usize, String> {
+        Self::update_asset_config(asset, |asset_config| {
+            let is_cleared = |tag: &str| release_tag.is_none_or(|release_tag| release_tag == tag);
+            let records = asset_config.failed_attempts.len() + asset_config.bad_releases.len();
+
+            asset_config
+                .failed_attempts
+                .retain(|attempt| !is_cleared(&attempt.release_tag));
+            asset_config
+                .bad_releases
+                .retain(|bad_release| !is_cleared(&bad_release.release_tag));
+
+            records - asset_config.failed_attempts.len() - asset_config.bad_releases.len()
+        })
+    }
+
+    fn update_asset_config<T>(
+        asset: &NymReleaseAssets,
+        update: impl FnOnce(&mut NymAssetUpdateConfig) -> T,
+    ) -> Result<T, String> {
         let mut config = Self::read_config_file()?;
-        config.release_tag = new_tag;
-        Self::_write_config_file(&config)
+        let asset_config = config
+            .assets
+            .iter_mut()
+            .find(|asset_config| asset_config.name == asset.name())
+            .ok_or_else(|| format!("{} is not defined in config file", asset.name()))?;
+
+        let res = update(asset_config);
+        Self::_write_config_file(&config)?;
+        Ok(res)
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NymReleaseConfig {
-    pub release_tag: String,
     pub assets: Vec<NymAssetUpdateConfig>,
+    #[serde(default)]
+    pub upstream: NymUpstreamConfig,
+    #[serde(default)]
+    pub release_channel: NymReleaseChannel,
+    //New releases are only adopted once they have been published for this long
+    #[serde(default)]
+    pub min_release_age_hours: u64,
+    //When not empty, every downloaded asset must carry a minisign signature made by one of these keys
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_public_keys: Vec<NymTrustedPublicKey>,
+    #[serde(default)]
+    pub install: NymInstallConfig,
+    #[serde(default)]
+    pub health_check: NymHealthCheckConfig,
+    #[serde(default)]
+    pub failure_policy: NymFailurePolicy,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NymAssetUpdateConfig {
     pub name: String,
     pub auto_update: bool,
+    #[serde(default)]
+    pub update_policy: NymUpdatePolicy,
+    //Release tag the asset must stay on, e.g. nym-binaries-v1.1.32
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_pin: Option<String>,
+    //Local HTTP API url that must answer after an update, e.g. http://127.0.0.1:8000/description
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed: Option<NymInstalledAsset>,
+    //Releases that crash looped after an update, they are not installed automatically again
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bad_releases: Vec<NymBadRelease>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed_attempts: Vec<NymFailedAttempt>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NymFailedAttempt {
+    pub release_tag: String,
+    pub failures: u32,
+    pub last_error: String,
+    pub last_failed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NymBadRelease {
+    pub release_tag: String,
+    pub reason: String,
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NymInstalledAsset {
+    pub release_tag: String,
+    pub build_version: String,
+    pub bin_path: String,
+    pub installed_at: DateTime<Utc>,
+}
+
+impl NymAssetUpdateConfig {
+    pub fn asset(&self) -> Result<NymReleaseAssets, String> {
+        NymReleaseAssets::from_name(&self.name)
+    }
+
+    pub fn is_bad_release(&self, release_tag: &str) -> bool {
+        self.bad_releases
+            .iter()
+            .any(|bad_release| bad_release.release_tag == release_tag)
+    }
+
+    pub fn failed_attempt(&self, release_tag: &str) -> Option<&NymFailedAttempt> {
+        self.failed_attempts
+            .iter()
+            .find(|attempt| attempt.release_tag == release_tag)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NymTrustedPublicKey {
+    pub name: String,
+    //Base64 minisign public key, e.g. the second line of a minisign.pub file
+    pub public_key: String,
+}
+
+//Highest semantic version level that is applied without an operator
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NymUpdatePolicy {
+    Patch,
+    Minor,
+    #[default]
+    Major,
+    Manual,
+}
+
+impl NymUpdatePolicy {
+    pub fn allows(&self, installed: &Version, target: &Version) -> bool {
+        match self {
+            NymUpdatePolicy::Patch => {
+                installed.major == target.major && installed.minor == target.minor
+            }
+            NymUpdatePolicy::Minor => installed.major == target.major,
+            NymUpdatePolicy::Major => true,
+            NymUpdatePolicy::Manual => false,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            NymUpdatePolicy::Patch => "patch",
+            NymUpdatePolicy::Minor => "minor",
+            NymUpdatePolicy::Major => "major",
+            NymUpdatePolicy::Manual => "manual",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NymReleaseChannel {
+    #[default]
+    Stable,
+    Prerelease,
+}
+
+impl NymReleaseChannel {
+    //Drafts are never installed, prereleases only on the prerelease channel
+    pub fn accepts(&self, release: &GithubRelease) -> bool {
+        if release.draft {
+            return false;
+        }
+
+        match self {
+            NymReleaseChannel::Stable => !release.prerelease,
+            NymReleaseChannel::Prerelease => true,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            NymReleaseChannel::Stable => "stable",
+            NymReleaseChannel::Prerelease => "prerelease",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NymUpstreamConfig {
+    pub owner: String,
+    pub repo: String,
+    //Release tags must match this pattern, its first capture group is the version
+    pub tag_pattern: String,
+    //GitHub Enterprise API url, e.g. https://github.example.com/api/v3
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_base_url: Option<String>,
+    //GitHub token is read from this env var first, then from this file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_env: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_file: Option<String>,
+}
+
+impl Default for NymUpstreamConfig {
+    fn default() -> Self {
+        Self {
+            owner: "nymtech".to_string(),
+            repo: "nym".to_string(),
+            tag_pattern: r"^nym-binaries-v(.+)$".to_string(),
+            api_base_url: None,
+            token_env: None,
+            token_file: None,
+        }
+    }
+}
+
+impl NymUpstreamConfig {
+    pub fn token(&self) -> Result<Option<String>, String> {
+        if let Some(token) = self
+            .token_env
+            .as_ref()
+            .and_then(|token_env| std::env::var(token_env).ok())
+            .filter(|token| !token.trim().is_empty())
+        {
+            return Ok(Some(token.trim().to_string()));
+        }
+
+        match &self.token_file {
+            Some(token_file) => {
+                let token = fs::read_to_string(token_file).map_err(|e| {
+                    format!(
+                        "Error while reading GitHub token file {} with {} error",
+                        token_file, e
+                    )
+                })?;
+                Ok(Some(token.trim().to_string()).filter(|token| !token.is_empty()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn tag_regex(&self) -> Result<Regex, String> {
+        let tag_regex = Regex::new(&self.tag_pattern).map_err(|e| {
+            format!(
+                "Invalid release tag pattern {} with {} error",
+                self.tag_pattern, e
+            )
+        })?;
+
+        if tag_regex.captures_len() < 2 {
+            return Err(format!(
+                "Release tag pattern {} has no capture group for the version",
+                self.tag_pattern
+            ));
+        }
+
+        Ok(tag_regex)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NymInstallConfig {
+    //Assets are installed to `<root>/<asset>/<release tag>/` and run through `<root>/<asset>/current`
+    pub root: String,
+    //Installed versions kept for rollback, older ones are pruned
+    pub keep_versions: usize,
+}
+
+impl Default for NymInstallConfig {
+    fn default() -> Self {
+        Self {
+            root: "/opt/nym".to_string(),
+            keep_versions: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NymHealthCheckConfig {
+    //Unit must stay active without restarts for this long after an update, 0 disables the check
+    pub stable_secs: u64,
+    //Time the asset health url has to answer once the unit is stable
+    pub http_timeout_secs: u64,
+    //Restarts within stable_secs after which a release is recorded as bad
+    pub crash_loop_restarts: u64,
+}
+
+impl Default for NymHealthCheckConfig {
+    fn default() -> Self {
+        Self {
+            stable_secs: 30,
+            http_timeout_secs: 30,
+            crash_loop_restarts: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NymFailurePolicy {
+    //Failed updates after which a release is not tried again until an operator runs `retry`
+    pub max_failures: u32,
+    //Wait after the first failure, doubled on every further failure up to backoff_max_secs
+    pub backoff_base_secs: u64,
+    pub backoff_max_secs: u64,
+}
+
+impl Default for NymFailurePolicy {
+    fn default() -> Self {
+        Self {
+            max_failures: 3,
+            backoff_base_secs: 3600,
+            backoff_max_secs: 24 * 3600,
+        }
+    }
+}
+
+impl NymFailurePolicy {
+    pub fn is_quarantined(&self, failed_attempt: &NymFailedAttempt) -> bool {
+        failed_attempt.failures >= self.max_failures
+    }
+
+    pub fn retry_at(&self, failed_attempt: &NymFailedAttempt) -> DateTime<Utc> {
+        let backoff_secs = 2u64
+            .saturating_pow(failed_attempt.failures.saturating_sub(1))
+            .saturating_mul(self.backoff_base_secs)
+            .min(self.backoff_max_secs);
+
+        failed_attempt.last_failed_at + Duration::seconds(backoff_secs as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    #[test]
+    fn patch_policy_only_allows_patch_updates() {
+        let installed = version("1.1.32");
+        assert!(NymUpdatePolicy::Patch.allows(&installed, &version("1.1.33")));
+        assert!(!NymUpdatePolicy::Patch.allows(&installed, &version("1.2.0")));
+        assert!(!NymUpdatePolicy::Patch.allows(&installed, &version("2.0.0")));
+    }
+
+    #[test]
+    fn minor_policy_allows_minor_and_patch_updates() {
+        let installed = version("1.1.32");
+        assert!(NymUpdatePolicy::Minor.allows(&installed, &version("1.1.33")));
+        assert!(NymUpdatePolicy::Minor.allows(&installed, &version("1.2.0")));
+        assert!(!NymUpdatePolicy::Minor.allows(&installed, &version("2.0.0")));
+    }
+
+    #[test]
+    fn major_and_manual_policies() {
+        let installed = version("2023.5.0-rolo");
+        let target = version("2024.13.0-magura");
+        assert!(NymUpdatePolicy::Major.allows(&installed, &target));
+        assert!(!NymUpdatePolicy::Manual.allows(&installed, &version("2023.5.1-rolo")));
+    }
+
+    #[test]
+    fn update_policy_defaults_to_major() {
+        assert_eq!(NymUpdatePolicy::default(), NymUpdatePolicy::Major);
+        let policy = serde_json::from_str::<NymUpdatePolicy>(r#""minor""#).unwrap();
+        assert_eq!(policy, NymUpdatePolicy::Minor);
+    }
+
+    fn failed_attempt(failures: u32) -> NymFailedAttempt {
+        NymFailedAttempt {
+            release_tag: "nym-binaries-v1.1.32".to_string(),
+            failures,
+            last_error: "unhealthy".to_string(),
+            last_failed_at: DateTime::<Utc>::from_timestamp(1_700_000_000, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn retry_backoff_doubles_up_to_max() {
+        let failure_policy = NymFailurePolicy {
+            max_failures: 10,
+            backoff_base_secs: 3600,
+            backoff_max_secs: 4 * 3600,
+        };
+        let backoff_hours = |failures| {
+            let failed_attempt = failed_attempt(failures);
+            (failure_policy.retry_at(&failed_attempt) - failed_attempt.last_failed_at).num_hours()
+        };
+
+        assert_eq!(backoff_hours(1), 1);
+        assert_eq!(backoff_hours(2), 2);
+        assert_eq!(backoff_hours(3), 4);
+        assert_eq!(backoff_hours(4), 4);
+        //Huge failure counts saturate instead of overflowing
+        assert_eq!(backoff_hours(u32::MAX), 4);
+    }
+
+    #[test]
+    fn quarantines_after_max_failures() {
+        let failure_policy = NymFailurePolicy::default();
+        assert!(!failure_policy.is_quarantined(&failed_attempt(failure_policy.max_failures - 1)));
+        assert!(failure_policy.is_quarantined(&failed_attempt(failure_policy.max_failures)));
+    }
 }
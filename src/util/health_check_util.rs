@@ -0,0 +1,308 @@
+use std::time::Duration;
+
+use tokio::time::{sleep, Instant};
+use tracing::info;
+
+use crate::{
+    appclient::{AppClient, RestResponse},
+    constants::NymReleaseAssets,
+};
+
+use super::{NymHealthCheckConfig, NymSystemdFileUtil};
+
+const HEALTH_POLL_SECS: u64 = 2;
+
+//Health gate an updated asset must pass before the update counts as successful
+pub struct NymHealthCheckUtil {
+    asset: NymReleaseAssets,
+    config: NymHealthCheckConfig,
+    health_url: Option<String>,
+}
+
+impl NymHealthCheckUtil {
+    pub fn new(
+        asset: NymReleaseAssets,
+        config: NymHealthCheckConfig,
+        health_url: Option<String>,
+    ) -> Self {
+        Self {
+            asset,
+            config,
+            health_url,
+        }
+    }
+
+    pub fn restart_count(&self) -> Result<u64, String> {
+        let restarts = NymSystemdFileUtil::new(self.asset.clone()).show_property("NRestarts")?;
+        restarts.trim().parse::<u64>().map_err(|e| {
+            format!(
+                "Invalid {} NRestarts value {} with {} error",
+                self.asset.name(),
+                restarts,
+                e
+            )
+        })
+    }
+
+    //restarts_before is the NRestarts value of the unit right after it was started
+    pub async fn verify(&self, restarts_before: u64) -> Result<(), NymHealthCheckError> {
+        self.verify_unit_stable(restarts_before).await?;
+        self.verify_health_url()
+            .await
+            .map_err(NymHealthCheckError::Unhealthy)?;
+
+        info!("{} passed health check", self.asset.name());
+        Ok(())
+    }
+
+    fn unit_status(&self) -> Result<NymUnitStatus, String> {
+        let systemd_manager = NymSystemdFileUtil::new(self.asset.clone());
+        Ok(NymUnitStatus {
+            active_state: systemd_manager.show_property("ActiveState")?,
+            sub_state: systemd_manager.show_property("SubState")?,
+            exec_main_status: systemd_manager.show_property("ExecMainStatus")?,
+            restarts: self.restart_count()?,
+        })
+    }
+
+    //Watches the unit for the whole window, so a node crash looping under `Restart=always` is caught
+    //even though it looks active most of the time
+    async fn verify_unit_stable(&self, restarts_before: u64) -> Result<(), NymHealthCheckError> {
+        let asset_name = self.asset.name();
+        if self.config.stable_secs == 0 {
+            return Ok(());
+        }
+
+        info!(
+            "Checking {} stays active for {} seconds...",
+            asset_name, self.config.stable_secs
+        );
+        let stable_until = Instant::now() + Duration::from_secs(self.config.stable_secs);
+        loop {
+            let unit_status = self.unit_status().map_err(NymHealthCheckError::Unhealthy)?;
+            let restarts = unit_status.restarts.saturating_sub(restarts_before);
+
+            if restarts >= self.config.crash_loop_restarts
+                || (restarts > 0 && unit_status.active_state == "failed")
+            {
+                return Err(NymHealthCheckError::CrashLoop(format!(
+                    "{} is crash looping, restarted {} times after update ({})",
+                    asset_name,
+                    restarts,
+                    unit_status.describe()
+                )));
+            }
+
+            //Unit that stopped without being restarted will not come back
+            if restarts == 0 && matches!(unit_status.active_state.as_str(), "failed" | "inactive") {
+                return Err(NymHealthCheckError::Unhealthy(format!(
+                    "{} stopped after update ({})",
+                    asset_name,
+                    unit_status.describe()
+                )));
+            }
+
+            if Instant::now() >= stable_until {
+                if restarts > 0 || unit_status.active_state != "active" {
+                    return Err(NymHealthCheckError::Unhealthy(format!(
+                        "{} is not stable after update, restarted {} times ({})",
+                        asset_name,
+                        restarts,
+                        unit_status.describe()
+                    )));
+                }
+                return Ok(());
+            }
+            sleep(Duration::from_secs(HEALTH_POLL_SECS)).await;
+        }
+    }
+
+    async fn verify_health_url(&self) -> Result<(), String> {
+        let Some(health_url) = &self.health_url else {
+            return Ok(());
+        };
+
+        info!("Waiting for {} to answer...", health_url);
+        let client = AppClient::new(String::new());
+        let timeout_at = Instant::now() + Duration::from_secs(self.config.http_timeout_secs);
+        loop {
+            //A node that accepts the connection but never answers must not outlast the timeout
+            let request_timeout = timeout_at
+                .saturating_duration_since(Instant::now())
+                .max(Duration::from_secs(1));
+            let last_err = match client
+                .get_text_with_timeout(health_url, request_timeout)
+                .await
+            {
+                Ok(RestResponse::Success(_)) => {
+                    info!("{} answered", health_url);
+                    return Ok(());
+                }
+                Ok(RestResponse::Error { message }) => message,
+                Ok(RestResponse::RateLimited { reset_at }) => {
+                    format!("rate limited until {}", reset_at)
+                }
+                Err(e) => e,
+            };
+
+            if Instant::now() >= timeout_at {
+                return Err(format!(
+                    "{} health url {} did not answer: {}",
+                    self.asset.name(),
+                    health_url,
+                    last_err
+                ));
+            }
+            sleep(Duration::from_secs(HEALTH_POLL_SECS)).await;
+        }
+    }
+}
+
+pub enum NymHealthCheckError {
+    //Node keeps being restarted by systemd, its release should not be tried again
+    CrashLoop(String),
+    Unhealthy(String),
+}
+
+struct NymUnitStatus {
+    active_state: String,
+    sub_state: String,
+    exec_main_status: String,
+    restarts: u64,
+}
+
+impl NymUnitStatus {
+    fn describe(&self) -> String {
+        format!(
+            "ActiveState={} SubState={} ExecMainStatus={} NRestarts={}",
+            self.active_state, self.sub_state, self.exec_main_status, self.restarts
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        time::timeout,
+    };
+
+    use super::*;
+
+    //Stand-in for a node HTTP API. Each connection gets the next status, the last one repeats,
+    //and None accepts the connection without ever answering
+    async fn stand_in_server(statuses: Vec<Option<u16>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let statuses = Arc::new(Mutex::new(statuses));
+
+        tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    return;
+                };
+                let status = {
+                    let mut statuses = statuses.lock().unwrap();
+                    if statuses.len() > 1 {
+                        statuses.remove(0)
+                    } else {
+                        statuses[0]
+                    }
+                };
+                tokio::spawn(answer(stream, status));
+            }
+        });
+
+        format!("http://{}/description", address)
+    }
+
+    async fn answer(mut stream: TcpStream, status: Option<u16>) {
+        let mut request = vec![];
+        let mut buf = [0u8; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            match stream.read(&mut buf).await {
+                Ok(0) | Err(_) => return,
+                Ok(read) => request.extend_from_slice(&buf[..read]),
+            }
+        }
+
+        let Some(status) = status else {
+            //Keeps the connection open without answering until the client gives up
+            let _ = stream.read(&mut buf).await;
+            return;
+        };
+        let body = format!("status {}", status);
+        let response = format!(
+            "HTTP/1.1 {} Stand-in\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        let _ = stream.write_all(response.as_bytes()).await;
+    }
+
+    fn health_check(health_url: Option<String>, http_timeout_secs: u64) -> NymHealthCheckUtil {
+        //Unit stability needs systemd, only the health url is checked here
+        let config = NymHealthCheckConfig {
+            stable_secs: 0,
+            http_timeout_secs,
+            crash_loop_restarts: 3,
+        };
+        NymHealthCheckUtil::new(NymReleaseAssets::MixNode, config, health_url)
+    }
+
+    async fn verify(health_check: NymHealthCheckUtil) -> Result<(), NymHealthCheckError> {
+        timeout(Duration::from_secs(20), health_check.verify(0))
+            .await
+            .expect("health gate must end within its timeout")
+    }
+
+    #[tokio::test]
+    async fn passes_when_health_url_answers() {
+        let health_url = stand_in_server(vec![Some(200)]).await;
+        assert!(verify(health_check(Some(health_url), 5)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn passes_without_health_url() {
+        assert!(verify(health_check(None, 5)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn waits_for_node_to_come_up() {
+        let health_url = stand_in_server(vec![Some(503), Some(200)]).await;
+        assert!(verify(health_check(Some(health_url), 10)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn fails_when_health_url_keeps_erroring() {
+        let health_url = stand_in_server(vec![Some(500)]).await;
+        match verify(health_check(Some(health_url), 1)).await {
+            Err(NymHealthCheckError::Unhealthy(e)) => assert!(e.contains("status 500"), "{}", e),
+            _ => panic!("health gate must fail when the node keeps erroring"),
+        }
+    }
+
+    #[tokio::test]
+    async fn fails_when_node_never_answers() {
+        let health_url = stand_in_server(vec![None]).await;
+        let started_at = Instant::now();
+        let res = verify(health_check(Some(health_url), 2)).await;
+
+        assert!(matches!(res, Err(NymHealthCheckError::Unhealthy(_))));
+        assert!(started_at.elapsed() < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn fails_when_nothing_listens() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let health_url = format!("http://{}/", listener.local_addr().unwrap());
+        drop(listener);
+
+        let res = verify(health_check(Some(health_url), 1)).await;
+        assert!(matches!(res, Err(NymHealthCheckError::Unhealthy(_))));
+    }
+}
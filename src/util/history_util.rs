@@ -0,0 +1,354 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use super::{NymPhaseRecord, NymUpdatePhase, NymUpdateTransaction};
+
+const HISTORY_DIR: &str = "./history";
+const HISTORY_FILE_NAME: &str = "updates.jsonl";
+
+//Every update run is appended as one JSON line, so the journal is never rewritten
+pub struct NymHistoryUtil {}
+
+impl NymHistoryUtil {
+    fn history_path() -> String {
+        format!("{}/{}", HISTORY_DIR, HISTORY_FILE_NAME)
+    }
+
+    pub fn append_record(record: &NymUpdateRecord) -> Result<(), String> {
+        fs::create_dir_all(HISTORY_DIR).map_err(|e| {
+            format!(
+                "Error while creating history dir {} with {} error",
+                HISTORY_DIR, e
+            )
+        })?;
+
+        let history_path = Self::history_path();
+        let mut line = serde_json::to_string(record)
+            .map_err(|e| format!("Error while serializing update record with {} error", e))?;
+        line.push('\n');
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&history_path)
+            .and_then(|mut history_file| history_file.write_all(line.as_bytes()))
+            .map_err(|e| format!("Error while writing file {} with {} error", history_path, e))
+    }
+
+    //Oldest record first. A line that can not be parsed is skipped, the rest of the journal stays usable
+    pub fn read_records() -> Result<Vec<NymUpdateRecord>, String> {
+        let history_path = Self::history_path();
+        let history_file = match fs::read_to_string(&history_path) {
+            Ok(history_file) => history_file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => {
+                return Err(format!(
+                    "Error while reading file {} with {} error",
+                    history_path, e
+                ))
+            }
+        };
+
+        let records = history_file
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .filter_map(|(index, line)| {
+                serde_json::from_str::<NymUpdateRecord>(line)
+                    .map_err(|e| {
+                        error!(
+                            "Ignoring invalid line {} of {}: {}",
+                            index + 1,
+                            history_path,
+                            e
+                        )
+                    })
+                    .ok()
+            })
+            .collect();
+
+        Ok(records)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NymUpdateOutcome {
+    Success,
+    NotNecessary,
+    RolledBack,
+    Failure,
+}
+
+impl NymUpdateOutcome {
+    pub fn as_str(&self) -> &str {
+        match self {
+            NymUpdateOutcome::Success => "success",
+            NymUpdateOutcome::NotNecessary => "not_necessary",
+            NymUpdateOutcome::RolledBack => "rolled_back",
+            NymUpdateOutcome::Failure => "failure",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NymRollbackRecord {
+    //Release tag current was pointed back at, if the previous version lived under the install root
+    pub release_tag: Option<String>,
+    pub exec_start: String,
+    pub succeeded: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub finished_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NymUpdateRecord {
+    pub asset: String,
+    pub from_release_tag: Option<String>,
+    pub from_version: Option<String>,
+    pub to_release_tag: String,
+    pub to_version: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    #[serde(default)]
+    pub phases: Vec<NymPhaseRecord>,
+    //Phase that was being taken when the update failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed_phase: Option<NymUpdatePhase>,
+    //Seconds between the service being stopped and running again, None when it was not running before
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub downtime_secs: Option<i64>,
+    pub outcome: NymUpdateOutcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollback: Option<NymRollbackRecord>,
+    //Finished by a later updater run after the one that started it was killed
+    #[serde(default)]
+    pub recovered: bool,
+}
+
+impl NymUpdateRecord {
+    pub fn from_transaction(
+        transaction: &NymUpdateTransaction,
+        outcome: NymUpdateOutcome,
+        error: Option<String>,
+        recovered: bool,
+    ) -> Self {
+        let phase_at = |phase: NymUpdatePhase| {
+            transaction
+                .phases
+                .iter()
+                .find(|phase_record| phase_record.phase == phase)
+                .map(|phase_record| phase_record.completed_at)
+        };
+
+        let running_again_at = match outcome {
+            NymUpdateOutcome::Success => phase_at(NymUpdatePhase::Started),
+            _ => transaction
+                .rollback
+                .as_ref()
+                .filter(|rollback| rollback.succeeded)
+                .map(|rollback| rollback.finished_at),
+        };
+        let downtime_secs = if transaction.was_running {
+            phase_at(NymUpdatePhase::Stopped)
+                .zip(running_again_at)
+                .map(|(stopped_at, running_again_at)| (running_again_at - stopped_at).num_seconds())
+        } else {
+            None
+        };
+
+        let failed_phase = match outcome {
+            NymUpdateOutcome::RolledBack | NymUpdateOutcome::Failure => transaction.phase.next(),
+            _ => None,
+        };
+
+        Self {
+            asset: transaction.asset.clone(),
+            from_release_tag: transaction.from_release_tag.clone(),
+            from_version: transaction.from_version.clone(),
+            to_release_tag: transaction.release_tag.clone(),
+            to_version: transaction.target_version.clone(),
+            started_at: transaction.started_at,
+            finished_at: Utc::now(),
+            phases: transaction.phases.clone(),
+            failed_phase,
+            downtime_secs,
+            outcome,
+            error,
+            rollback: transaction.rollback.clone(),
+            recovered,
+        }
+    }
+
+    //Run that failed before its transaction was written, so nothing was changed by it
+    pub fn failed_start(
+        asset: &str,
+        from_release_tag: Option<String>,
+        from_version: Option<String>,
+        to_release_tag: &str,
+        started_at: DateTime<Utc>,
+        error: &str,
+    ) -> Self {
+        Self {
+            asset: asset.to_string(),
+            from_release_tag,
+            from_version,
+            to_release_tag: to_release_tag.to_string(),
+            to_version: None,
+            started_at,
+            finished_at: Utc::now(),
+            phases: vec![],
+            failed_phase: Some(NymUpdatePhase::Downloaded),
+            downtime_secs: None,
+            outcome: NymUpdateOutcome::Failure,
+            error: Some(error.to_string()),
+            rollback: None,
+            recovered: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::util::NymSystemdUnitSnapshot;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::<Utc>::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    //Transaction that passed the given phases, each completed at its offset in seconds
+    fn transaction(phases: &[(NymUpdatePhase, i64)], was_running: bool) -> NymUpdateTransaction {
+        let phases = phases
+            .iter()
+            .map(|(phase, secs)| NymPhaseRecord {
+                phase: *phase,
+                completed_at: at(*secs),
+            })
+            .collect::<Vec<_>>();
+
+        NymUpdateTransaction {
+            asset: "nym-mixnode".to_string(),
+            from_release_tag: Some("nym-binaries-v1.1.31".to_string()),
+            from_version: Some("1.1.31".to_string()),
+            release_tag: "nym-binaries-v1.1.32".to_string(),
+            store_path: "./store/nym-binaries-v1.1.32/nym-mixnode".to_string(),
+            target_path: "/opt/nym/nym-mixnode/nym-binaries-v1.1.32/nym-mixnode".to_string(),
+            target_version: Some("1.1.32".to_string()),
+            previous_version: Some("nym-binaries-v1.1.31".to_string()),
+            unit_snapshot: NymSystemdUnitSnapshot {
+                exec_start: "/opt/nym/nym-mixnode/current/nym-mixnode run --id node".to_string(),
+                unit_file: String::new(),
+            },
+            was_running,
+            phase: phases.last().map(|record| record.phase).unwrap(),
+            phases,
+            rollback: None,
+            started_at: at(0),
+            updated_at: at(0),
+        }
+    }
+
+    const APPLIED_PHASES: [(NymUpdatePhase, i64); 7] = [
+        (NymUpdatePhase::Downloaded, 10),
+        (NymUpdatePhase::Verified, 11),
+        (NymUpdatePhase::Stopped, 20),
+        (NymUpdatePhase::Initialized, 25),
+        (NymUpdatePhase::UnitRewritten, 26),
+        (NymUpdatePhase::Started, 32),
+        (NymUpdatePhase::HealthChecked, 62),
+    ];
+
+    #[test]
+    fn success_downtime_ends_when_service_is_started() {
+        let transaction = transaction(&APPLIED_PHASES, true);
+        let record =
+            NymUpdateRecord::from_transaction(&transaction, NymUpdateOutcome::Success, None, false);
+
+        assert_eq!(record.downtime_secs, Some(12));
+        assert_eq!(record.failed_phase, None);
+        assert_eq!(record.phases.len(), 7);
+        assert_eq!(record.to_version.as_deref(), Some("1.1.32"));
+    }
+
+    #[test]
+    fn rolled_back_downtime_ends_when_rollback_finishes() {
+        let mut transaction = transaction(&APPLIED_PHASES[..6], true);
+        transaction.rollback = Some(NymRollbackRecord {
+            release_tag: Some("nym-binaries-v1.1.31".to_string()),
+            exec_start: transaction.unit_snapshot.exec_start.clone(),
+            succeeded: true,
+            error: None,
+            finished_at: at(20) + Duration::seconds(45),
+        });
+        let record = NymUpdateRecord::from_transaction(
+            &transaction,
+            NymUpdateOutcome::RolledBack,
+            Some("node is unhealthy".to_string()),
+            true,
+        );
+
+        assert_eq!(record.downtime_secs, Some(45));
+        assert_eq!(record.failed_phase, Some(NymUpdatePhase::HealthChecked));
+        assert!(record.recovered);
+    }
+
+    #[test]
+    fn failed_rollback_has_no_downtime_end() {
+        let mut transaction = transaction(&APPLIED_PHASES[..4], true);
+        transaction.rollback = Some(NymRollbackRecord {
+            release_tag: None,
+            exec_start: transaction.unit_snapshot.exec_start.clone(),
+            succeeded: false,
+            error: Some("unit can not be restored".to_string()),
+            finished_at: at(40),
+        });
+        let record = NymUpdateRecord::from_transaction(
+            &transaction,
+            NymUpdateOutcome::Failure,
+            Some("init failed".to_string()),
+            false,
+        );
+
+        assert_eq!(record.downtime_secs, None);
+        assert_eq!(record.failed_phase, Some(NymUpdatePhase::UnitRewritten));
+    }
+
+    #[test]
+    fn not_running_service_has_no_downtime() {
+        let transaction = transaction(&APPLIED_PHASES, false);
+        let record =
+            NymUpdateRecord::from_transaction(&transaction, NymUpdateOutcome::Success, None, false);
+
+        assert_eq!(record.downtime_secs, None);
+        assert_eq!(record.failed_phase, None);
+    }
+
+    #[test]
+    fn failed_start_changed_nothing() {
+        let record = NymUpdateRecord::failed_start(
+            "nym-mixnode",
+            None,
+            None,
+            "nym-binaries-v1.1.32",
+            at(0),
+            "Error while reloading systemd daemon",
+        );
+
+        assert_eq!(record.outcome, NymUpdateOutcome::Failure);
+        assert_eq!(record.failed_phase, Some(NymUpdatePhase::Downloaded));
+        assert_eq!(record.downtime_secs, None);
+        assert!(record.phases.is_empty());
+    }
+}
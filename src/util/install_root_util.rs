@@ -0,0 +1,164 @@
+use std::{cmp::Reverse, fs, path::Path, time::SystemTime};
+
+use cmd_lib::run_fun;
+use tracing::{error, info, warn};
+
+use crate::constants::NymReleaseAssets;
+
+use super::{NymChecksumUtil, NymInstallConfig};
+
+const CURRENT_LINK_NAME: &str = "current";
+
+//Each release of an asset lives in `<root>/<asset>/<release tag>/<asset>` and the systemd unit
+//runs `<root>/<asset>/current/<asset>`, so switching versions is a single symlink flip.
+//The install root is owned by root, so every change goes through sudo like the unit file edits
+pub struct NymInstallRootUtil {
+    asset: NymReleaseAssets,
+    asset_root: String,
+}
+
+impl NymInstallRootUtil {
+    pub fn new(asset: NymReleaseAssets, install_config: &NymInstallConfig) -> Self {
+        let asset_root = format!(
+            "{}/{}",
+            install_config.root.trim_end_matches('/'),
+            asset.name()
+        );
+        Self { asset, asset_root }
+    }
+
+    pub fn version_bin_path(&self, release_tag: &str) -> String {
+        format!("{}/{}/{}", self.asset_root, release_tag, self.asset.name())
+    }
+
+    pub fn current_bin_path(&self) -> String {
+        format!(
+            "{}/{}/{}",
+            self.asset_root,
+            CURRENT_LINK_NAME,
+            self.asset.name()
+        )
+    }
+
+    pub fn current_link_path(&self) -> String {
+        format!("{}/{}", self.asset_root, CURRENT_LINK_NAME)
+    }
+
+    //Copies a verified asset into its version dir. An installed version is only kept when it still
+    //matches the verified asset
+    pub fn install_version(&self, release_tag: &str, source_path: &str) -> Result<String, String> {
+        let version_dir = format!("{}/{}", self.asset_root, release_tag);
+        let bin_path = self.version_bin_path(release_tag);
+        if Path::new(&bin_path).exists() {
+            let source_sha256 = NymChecksumUtil::file_sha256(source_path)?;
+            match NymChecksumUtil::file_sha256(&bin_path) {
+                Ok(installed_sha256) if installed_sha256 == source_sha256 => {
+                    info!("{} is already installed", bin_path);
+                    return Ok(bin_path);
+                }
+                Ok(installed_sha256) => warn!(
+                    "Installed {} has SHA-256 {} instead of {}, installing it again",
+                    bin_path, installed_sha256, source_sha256
+                ),
+                Err(e) => warn!("{}, installing it again", e),
+            }
+        }
+
+        run_fun!(sudo mkdir -p $version_dir).map_err(|e| {
+            format!(
+                "Error while creating install dir {} with {} error",
+                version_dir, e
+            )
+        })?;
+
+        let tmp_path = format!("{}.tmp", bin_path);
+        run_fun!(sudo install -m 755 $source_path $tmp_path).map_err(|e| {
+            format!(
+                "Error while copying {} to {} with {} error",
+                source_path, tmp_path, e
+            )
+        })?;
+        run_fun!(sudo mv -f $tmp_path $bin_path).map_err(|e| {
+            format!(
+                "Error while moving {} to {} with {} error",
+                tmp_path, bin_path, e
+            )
+        })?;
+
+        info!("Installed {} to {}", source_path, bin_path);
+        Ok(bin_path)
+    }
+
+    //Release tag the current symlink points at, if any
+    pub fn current_version(&self) -> Option<String> {
+        fs::read_link(self.current_link_path())
+            .ok()
+            .and_then(|target| target.file_name()?.to_str().map(|tag| tag.to_string()))
+    }
+
+    pub fn switch_current(&self, release_tag: &str) -> Result<(), String> {
+        let current_link_path = self.current_link_path();
+        let tmp_link_path = format!("{}.tmp", current_link_path);
+
+        //Rename over the old link is atomic, so current always points at a complete version.
+        //-n and -T keep ln and mv from following the links into the version dirs
+        run_fun!(sudo ln -sfn $release_tag $tmp_link_path).map_err(|e| {
+            format!(
+                "Error while creating symlink {} with {} error",
+                tmp_link_path, e
+            )
+        })?;
+        run_fun!(sudo mv -fT $tmp_link_path $current_link_path).map_err(|e| {
+            format!(
+                "Error while moving {} to {} with {} error",
+                tmp_link_path, current_link_path, e
+            )
+        })?;
+
+        info!("{} now points at {}", current_link_path, release_tag);
+        Ok(())
+    }
+
+    //Removes all but the newest keep_versions versions, never the current one. Returns removed tags
+    pub fn prune_versions(&self, keep_versions: usize) -> Result<Vec<String>, String> {
+        let current_version = self.current_version();
+        let entries = fs::read_dir(&self.asset_root).map_err(|e| {
+            format!(
+                "Error while reading install dir {} with {} error",
+                self.asset_root, e
+            )
+        })?;
+
+        let mut versions = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+            .filter_map(|entry| {
+                let release_tag = entry.file_name().to_str()?.to_string();
+                let modified = entry
+                    .metadata()
+                    .and_then(|metadata| metadata.modified())
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                Some((release_tag, modified))
+            })
+            .collect::<Vec<_>>();
+        versions.sort_by_key(|(_, modified)| Reverse(*modified));
+
+        let mut removed = vec![];
+        for (release_tag, _) in versions.into_iter().skip(keep_versions) {
+            if current_version.as_ref() == Some(&release_tag) {
+                continue;
+            }
+
+            let version_dir = format!("{}/{}", self.asset_root, release_tag);
+            match run_fun!(sudo rm -rf $version_dir) {
+                Ok(_) => {
+                    info!("Pruned {}", version_dir);
+                    removed.push(release_tag);
+                }
+                Err(e) => error!("Failed to prune {} with {} error", version_dir, e),
+            }
+        }
+
+        Ok(removed)
+    }
+}
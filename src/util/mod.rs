@@ -1,7 +1,25 @@
 mod app_logger;
+mod asset_store_util;
+mod checksum_util;
 mod config_file_util;
+mod health_check_util;
+mod history_util;
+mod install_root_util;
+mod release_cache_util;
+mod signature_util;
 mod systemd_file_util;
+mod update_state_util;
+mod version_util;
 
 pub use app_logger::*;
+pub use asset_store_util::*;
+pub use checksum_util::*;
 pub use config_file_util::*;
+pub use health_check_util::*;
+pub use history_util::*;
+pub use install_root_util::*;
+pub use release_cache_util::*;
+pub use signature_util::*;
 pub use systemd_file_util::*;
+pub use update_state_util::*;
+pub use version_util::*;
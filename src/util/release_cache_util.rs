@@ -0,0 +1,68 @@
+use std::fs;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::appclient::{CacheValidators, GithubReleasesResponse};
+
+use super::NymUpstreamConfig;
+
+const RELEASE_CACHE_DIR: &str = "./cache";
+
+pub struct NymReleaseCacheUtil {}
+
+impl NymReleaseCacheUtil {
+    pub fn cache_path(upstream: &NymUpstreamConfig) -> String {
+        format!(
+            "{}/releases-{}-{}.json",
+            RELEASE_CACHE_DIR, upstream.owner, upstream.repo
+        )
+    }
+
+    //A missing or unreadable cache is not an error, the release list is fetched again
+    pub fn read_release_cache(cache_path: &str) -> Option<NymReleaseCache> {
+        let cache_file = fs::read_to_string(cache_path).ok()?;
+        serde_json::from_str::<NymReleaseCache>(&cache_file)
+            .map_err(|e| error!("Ignoring invalid release cache {}: {}", cache_path, e))
+            .ok()
+    }
+
+    pub fn write_release_cache(cache_path: &str, cache: &NymReleaseCache) -> Result<(), String> {
+        fs::create_dir_all(RELEASE_CACHE_DIR).map_err(|e| {
+            format!(
+                "Error while creating cache dir {} with {} error",
+                RELEASE_CACHE_DIR, e
+            )
+        })?;
+
+        let cache_file = serde_json::to_string(cache)
+            .map_err(|e| format!("Error while serializing release cache with {} error", e))?;
+
+        //Written next to the cache and renamed, so a crash never leaves a truncated cache
+        let tmp_path = format!("{}.tmp", cache_path);
+        fs::write(&tmp_path, cache_file)
+            .map_err(|e| format!("Error while writing file {} with {} error", tmp_path, e))?;
+        fs::rename(&tmp_path, cache_path).map_err(|e| {
+            format!(
+                "Error while moving {} to {} with {} error",
+                tmp_path, cache_path, e
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NymReleaseCache {
+    #[serde(flatten)]
+    pub validators: CacheValidators,
+    //Last time GitHub confirmed the cached release list, either by sending it or by a 304
+    pub checked_at: DateTime<Utc>,
+    pub releases: GithubReleasesResponse,
+}
+
+impl NymReleaseCache {
+    pub fn age_hours(&self) -> i64 {
+        (Utc::now() - self.checked_at).num_hours()
+    }
+}
@@ -0,0 +1,56 @@
+use std::fs;
+
+use minisign_verify::{PublicKey, Signature};
+use tracing::{error, info};
+
+use super::{NymChecksumUtil, NymTrustedPublicKey};
+
+pub struct NymSignatureUtil {}
+
+impl NymSignatureUtil {
+    pub fn verify_file<'a>(
+        path: &str,
+        signature_content: &str,
+        trusted_keys: &'a [NymTrustedPublicKey],
+    ) -> Result<&'a NymTrustedPublicKey, String> {
+        let signature = Signature::decode(signature_content).map_err(|e| {
+            format!(
+                "Error while decoding signature of {} with {} error",
+                path, e
+            )
+        })?;
+        let file_content =
+            fs::read(path).map_err(|e| format!("Error while reading {} with {} error", path, e))?;
+
+        for trusted_key in trusted_keys {
+            let public_key = match PublicKey::from_base64(&trusted_key.public_key) {
+                Ok(public_key) => public_key,
+                Err(e) => {
+                    error!(
+                        "Trusted public key {} is invalid with {} error",
+                        trusted_key.name, e
+                    );
+                    continue;
+                }
+            };
+
+            if public_key.verify(&file_content, &signature, false).is_ok() {
+                info!(
+                    "Signature of {} verified with trusted key {} ({})",
+                    path,
+                    trusted_key.name,
+                    signature.trusted_comment()
+                );
+                return Ok(trusted_key);
+            }
+        }
+
+        let quarantine_path = NymChecksumUtil::quarantine_file(path)?;
+        let err = format!(
+            "No trusted key produced a valid signature for {}. File moved to {}",
+            path, quarantine_path
+        );
+        error!(err);
+        Err(err)
+    }
+}
@@ -1,6 +1,7 @@
-use std::path::Path;
+use std::{fs, path::Path};
 
 use cmd_lib::run_fun;
+use serde::{Deserialize, Serialize};
 use tracing::info;
 
 use crate::constants::NymReleaseAssets;
@@ -18,18 +19,30 @@ impl NymSystemdFileUtil {
         format!("/etc/systemd/system/{}.service", self.asset.name())
     }
 
-    pub fn _has_asset_service(&self) -> bool {
+    pub fn has_asset_service(&self) -> bool {
         Path::new(&self.get_service_path()).exists()
     }
 
     pub async fn current_exec_start_path(&self) -> Result<String, String> {
         let asset_name = self.asset.name();
-        let res = run_fun!(systemctl show -p ExecStart --value $asset_name | grep -o "path=[^;]*" | cut -d= -f2).map_err(|e| format!("Error while getting mixnode systemd path with {} error", e))?;
-        info!("Mixnode systemd path is {}", res);
+        let res = run_fun!(systemctl show -p ExecStart --value $asset_name | grep -o "path=[^;]*" | cut -d= -f2).map_err(|e| format!("Error while getting {} systemd path with {} error", asset_name, e))?;
+        info!("{} systemd path is {}", asset_name, res);
 
         Ok(res)
     }
 
+    pub fn show_property(&self, property: &str) -> Result<String, String> {
+        let asset_name = self.asset.name();
+        let value = run_fun!(systemctl show -p $property --value $asset_name).map_err(|e| {
+            format!(
+                "Error while getting {} systemd property {} with {} error",
+                asset_name, property, e
+            )
+        })?;
+
+        Ok(value.trim().to_string())
+    }
+
     pub async fn exec_start_full_line(&self) -> Result<String, String> {
         let asset_name = self.asset.name();
         let line =
@@ -43,16 +56,20 @@ impl NymSystemdFileUtil {
         Ok(line)
     }
 
-    pub async fn update_exec_start_prop(&self, new_path: String) -> Result<(), String> {
-        let asset_name = self.asset.name();
+    pub async fn exec_start_line_with_path(&self, new_path: &str) -> Result<String, String> {
         let current_exec_start_path = self.current_exec_start_path().await?;
         let current_full_exec_start_line = self.exec_start_full_line().await?;
 
-        let final_exec_start_line = current_full_exec_start_line.replace(
+        Ok(current_full_exec_start_line.replace(
             &current_exec_start_path,
             //With one space line to separate the path and the args
             format!("{} ", new_path).as_str(),
-        );
+        ))
+    }
+
+    pub async fn update_exec_start_prop(&self, new_path: String) -> Result<(), String> {
+        let asset_name = self.asset.name();
+        let final_exec_start_line = self.exec_start_line_with_path(&new_path).await?;
 
         let prop = NymSystemDProperty::ExecStart;
         self.set_service_property(&prop, &final_exec_start_line)?;
@@ -69,7 +86,7 @@ impl NymSystemdFileUtil {
 
     pub async fn update_description_prop(&self, version: String) -> Result<(), String> {
         let asset_name = self.asset.name();
-        let final_description = format!("Nym {} {}", asset_name, version);
+        let final_description = self.description(&version);
         let prop = NymSystemDProperty::Description;
         self.set_service_property(&prop, &final_description)?;
 
@@ -83,6 +100,67 @@ impl NymSystemdFileUtil {
         Ok(())
     }
 
+    pub fn description(&self, version: &str) -> String {
+        format!("Nym {} {}", self.asset.name(), version)
+    }
+
+    pub fn unit_snapshot(&self) -> Result<NymSystemdUnitSnapshot, String> {
+        let service_path = self.get_service_path();
+        let unit_file = fs::read_to_string(&service_path).map_err(|e| {
+            format!(
+                "Error while reading {} systemd file with {} error",
+                service_path, e
+            )
+        })?;
+
+        let prop_value = |prop: NymSystemDProperty| {
+            let prefix = format!("{}=", prop.as_str());
+            unit_file
+                .lines()
+                .find_map(|line| line.strip_prefix(&prefix))
+                .map(|value| value.to_string())
+                .ok_or_else(|| format!("{} has no {} property", service_path, prop.as_str()))
+        };
+
+        let snapshot = NymSystemdUnitSnapshot {
+            exec_start: prop_value(NymSystemDProperty::ExecStart)?,
+            unit_file: unit_file.clone(),
+        };
+
+        info!(
+            "{} systemd unit snapshot taken with ExecStart {}",
+            self.asset.name(),
+            snapshot.exec_start
+        );
+
+        Ok(snapshot)
+    }
+
+    //Restores the whole unit file, so anything changed after the snapshot is reverted
+    pub fn restore_unit_snapshot(&self, snapshot: &NymSystemdUnitSnapshot) -> Result<(), String> {
+        let service_path = self.get_service_path();
+        let tmp_path = format!("./{}.service.rollback", self.asset.name());
+        fs::write(&tmp_path, &snapshot.unit_file)
+            .map_err(|e| format!("Error while writing file {} with {} error", tmp_path, e))?;
+
+        let restored = run_fun!(sudo cp $tmp_path $service_path).map_err(|e| {
+            format!(
+                "Error while restoring {} systemd file with {} error",
+                service_path, e
+            )
+        });
+        let _ = fs::remove_file(&tmp_path);
+        restored?;
+
+        info!(
+            "{} systemd unit restored to ExecStart {}",
+            self.asset.name(),
+            snapshot.exec_start
+        );
+
+        Ok(())
+    }
+
     pub fn systemd_reload(&self) -> Result<(), String> {
         run_fun!(sudo systemctl daemon-reload)
             .map_err(|e| format!("Error while reloading systemd with {} error", e))?;
@@ -112,6 +190,12 @@ impl NymSystemdFileUtil {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NymSystemdUnitSnapshot {
+    pub exec_start: String,
+    pub unit_file: String,
+}
+
 #[derive(Debug)]
 pub enum NymSystemDProperty {
     Description,
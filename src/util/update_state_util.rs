@@ -0,0 +1,233 @@
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    path::Path,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::constants::NymReleaseAssets;
+
+use super::{NymRollbackRecord, NymSystemdUnitSnapshot};
+
+const UPDATE_STATE_DIR: &str = "./state";
+const UPDATE_LOCK_FILE_NAME: &str = "updater.lock";
+
+//An update in progress is kept in `<state>/<asset>.update.json` until it is finished or rolled
+//back, so an updater killed halfway knows what it already changed on the next start
+pub struct NymUpdateStateUtil {}
+
+impl NymUpdateStateUtil {
+    fn transaction_path(asset: &NymReleaseAssets) -> String {
+        format!("{}/{}.update.json", UPDATE_STATE_DIR, asset.name())
+    }
+
+    pub fn read_transaction(
+        asset: &NymReleaseAssets,
+    ) -> Result<Option<NymUpdateTransaction>, String> {
+        let transaction_path = Self::transaction_path(asset);
+        if !Path::new(&transaction_path).exists() {
+            return Ok(None);
+        }
+
+        let transaction_file = fs::read_to_string(&transaction_path).map_err(|e| {
+            format!(
+                "Error while reading file {} with {} error",
+                transaction_path, e
+            )
+        })?;
+        serde_json::from_str::<NymUpdateTransaction>(&transaction_file)
+            .map(Some)
+            .map_err(|e| {
+                format!(
+                    "Error while parsing update state {} with {} error",
+                    transaction_path, e
+                )
+            })
+    }
+
+    pub fn write_transaction(
+        asset: &NymReleaseAssets,
+        transaction: &NymUpdateTransaction,
+    ) -> Result<(), String> {
+        fs::create_dir_all(UPDATE_STATE_DIR).map_err(|e| {
+            format!(
+                "Error while creating state dir {} with {} error",
+                UPDATE_STATE_DIR, e
+            )
+        })?;
+
+        let transaction_path = Self::transaction_path(asset);
+        let transaction_file = serde_json::to_string_pretty(transaction)
+            .map_err(|e| format!("Error while serializing update state with {} error", e))?;
+
+        //Renamed over the previous state, so a crash never leaves a truncated phase behind
+        let tmp_path = format!("{}.tmp", transaction_path);
+        fs::write(&tmp_path, transaction_file)
+            .map_err(|e| format!("Error while writing file {} with {} error", tmp_path, e))?;
+        fs::rename(&tmp_path, &transaction_path).map_err(|e| {
+            format!(
+                "Error while moving {} to {} with {} error",
+                tmp_path, transaction_path, e
+            )
+        })
+    }
+
+    fn lock_path() -> String {
+        format!("{}/{}", UPDATE_STATE_DIR, UPDATE_LOCK_FILE_NAME)
+    }
+
+    //Only the process holding the lock may change assets or settle their transactions. It fails
+    //right away when another updater holds it, the kernel releases it when the holder dies
+    pub fn acquire_lock() -> Result<NymUpdateLock, String> {
+        fs::create_dir_all(UPDATE_STATE_DIR).map_err(|e| {
+            format!(
+                "Error while creating state dir {} with {} error",
+                UPDATE_STATE_DIR, e
+            )
+        })?;
+
+        let lock_path = Self::lock_path();
+        let lock_file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .map_err(|e| format!("Error while opening file {} with {} error", lock_path, e))?;
+
+        match lock_file.try_lock() {
+            Ok(()) => Ok(NymUpdateLock {
+                _lock_file: lock_file,
+            }),
+            Err(TryLockError::WouldBlock) => Err(format!(
+                "Another updater is running, {} is locked",
+                lock_path
+            )),
+            Err(TryLockError::Error(e)) => Err(format!(
+                "Error while locking file {} with {} error",
+                lock_path, e
+            )),
+        }
+    }
+
+    //Whether another updater holds the lock, so its transactions are in progress and not left behind
+    pub fn is_locked() -> bool {
+        let Ok(lock_file) = File::open(Self::lock_path()) else {
+            return false;
+        };
+
+        matches!(lock_file.try_lock(), Err(TryLockError::WouldBlock))
+    }
+
+    pub fn remove_transaction(asset: &NymReleaseAssets) -> Result<(), String> {
+        let transaction_path = Self::transaction_path(asset);
+        match fs::remove_file(&transaction_path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(format!(
+                "Error while removing file {} with {} error",
+                transaction_path, e
+            )),
+        }
+    }
+}
+
+//Lock of the running updater, released when dropped
+#[derive(Debug)]
+pub struct NymUpdateLock {
+    _lock_file: File,
+}
+
+//Last step an update completed, in the order they are taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum NymUpdatePhase {
+    Downloaded,
+    Verified,
+    Stopped,
+    Initialized,
+    UnitRewritten,
+    Started,
+    HealthChecked,
+}
+
+impl NymUpdatePhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NymUpdatePhase::Downloaded => "Downloaded",
+            NymUpdatePhase::Verified => "Verified",
+            NymUpdatePhase::Stopped => "Stopped",
+            NymUpdatePhase::Initialized => "Initialized",
+            NymUpdatePhase::UnitRewritten => "UnitRewritten",
+            NymUpdatePhase::Started => "Started",
+            NymUpdatePhase::HealthChecked => "HealthChecked",
+        }
+    }
+
+    pub fn next(&self) -> Option<NymUpdatePhase> {
+        match self {
+            NymUpdatePhase::Downloaded => Some(NymUpdatePhase::Verified),
+            NymUpdatePhase::Verified => Some(NymUpdatePhase::Stopped),
+            NymUpdatePhase::Stopped => Some(NymUpdatePhase::Initialized),
+            NymUpdatePhase::Initialized => Some(NymUpdatePhase::UnitRewritten),
+            NymUpdatePhase::UnitRewritten => Some(NymUpdatePhase::Started),
+            NymUpdatePhase::Started => Some(NymUpdatePhase::HealthChecked),
+            NymUpdatePhase::HealthChecked => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NymPhaseRecord {
+    pub phase: NymUpdatePhase,
+    pub completed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NymUpdateTransaction {
+    pub asset: String,
+    //Installed release recorded in config when the update started
+    pub from_release_tag: Option<String>,
+    pub from_version: Option<String>,
+    pub release_tag: String,
+    pub store_path: String,
+    pub target_path: String,
+    //Build version of the downloaded asset, known once it is verified
+    pub target_version: Option<String>,
+    //Release tag current pointed at before the update, if it was installed under the install root
+    pub previous_version: Option<String>,
+    pub unit_snapshot: NymSystemdUnitSnapshot,
+    pub was_running: bool,
+    pub phase: NymUpdatePhase,
+    #[serde(default)]
+    pub phases: Vec<NymPhaseRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollback: Option<NymRollbackRecord>,
+    pub started_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl NymUpdateTransaction {
+    //Persists the phase before anything depending on it happens
+    pub fn advance(
+        &mut self,
+        asset: &NymReleaseAssets,
+        phase: NymUpdatePhase,
+    ) -> Result<(), String> {
+        self.phase = phase;
+        self.updated_at = Utc::now();
+        self.phases.push(NymPhaseRecord {
+            phase,
+            completed_at: self.updated_at,
+        });
+        NymUpdateStateUtil::write_transaction(asset, self)?;
+
+        info!(
+            "{} update to {} reached phase {}",
+            self.asset,
+            self.release_tag,
+            phase.as_str()
+        );
+        Ok(())
+    }
+}
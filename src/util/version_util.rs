@@ -0,0 +1,114 @@
+use regex::Regex;
+use semver::Version;
+
+pub struct NymVersionUtil {}
+
+impl NymVersionUtil {
+    //Parses release tags like nym-binaries-v1.1.32 into their semantic version,
+    //using the first capture group of tag_regex as the version
+    pub fn parse_release_tag(tag: &str, tag_regex: &Regex) -> Result<Version, String> {
+        let version = tag_regex
+            .captures(tag)
+            .and_then(|captures| captures.get(1))
+            .ok_or_else(|| format!("Release tag {} does not match {}", tag, tag_regex))?;
+
+        Self::parse_version(version.as_str())
+            .map_err(|e| format!("Release tag {} has invalid version: {}", tag, e))
+    }
+
+    //Parses the `Build Version` line value printed by `<binary> --version`
+    pub fn parse_build_version(build_version: &str) -> Result<Version, String> {
+        Self::parse_version(build_version)
+            .map_err(|e| format!("Build version {} is invalid: {}", build_version, e))
+    }
+
+    fn parse_version(version: &str) -> Result<Version, String> {
+        let version = version.trim().trim_start_matches('v');
+        if let Ok(parsed) = Version::parse(version) {
+            return Ok(parsed);
+        }
+
+        //Some releases are tagged without a patch number, e.g. 2023.1-milka
+        let (core, suffix) = match version.split_once('-') {
+            Some((core, suffix)) => (core, Some(suffix)),
+            None => (version, None),
+        };
+        if core.split('.').count() != 2 {
+            return Err(format!("{} is not a semantic version", version));
+        }
+
+        let padded = match suffix {
+            Some(suffix) => format!("{}.0-{}", core, suffix),
+            None => format!("{}.0", core),
+        };
+        Version::parse(&padded).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(version: &str) -> Version {
+        NymVersionUtil::parse_build_version(version).unwrap()
+    }
+
+    #[test]
+    fn parses_full_semantic_versions() {
+        assert_eq!(version("1.1.32"), Version::new(1, 1, 32));
+        assert_eq!(version(" 1.1.32\n"), Version::new(1, 1, 32));
+    }
+
+    #[test]
+    fn strips_leading_v() {
+        assert_eq!(version("v1.1.32"), Version::new(1, 1, 32));
+        assert_eq!(version("v2024.13-magura"), version("2024.13-magura"));
+    }
+
+    #[test]
+    fn pads_missing_patch_number() {
+        assert_eq!(version("2024.13"), Version::new(2024, 13, 0));
+        assert_eq!(
+            version("2024.13-magura"),
+            Version::parse("2024.13.0-magura").unwrap()
+        );
+        assert_eq!(
+            version("2024.13-magura-patched"),
+            Version::parse("2024.13.0-magura-patched").unwrap()
+        );
+    }
+
+    #[test]
+    fn orders_suffixed_versions() {
+        assert!(version("2024.13-magura") < version("2024.13-magura-patched"));
+        assert!(version("2024.13-magura-patched") < version("2024.14-magura"));
+        //A suffix is a semver prerelease, so it sorts before the bare version
+        assert!(version("2024.13-magura") < version("2024.13"));
+        assert!(version("1.1.9") < version("1.1.10"));
+    }
+
+    #[test]
+    fn rejects_invalid_versions() {
+        for invalid in ["", "1", "1.2.3.4", "abc", "1.x", "1.x-magura", "v"] {
+            assert!(
+                NymVersionUtil::parse_build_version(invalid).is_err(),
+                "{} should be rejected",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn parses_release_tags_with_capture_group() {
+        let tag_regex = Regex::new(r"^nym-binaries-v(.+)$").unwrap();
+        assert_eq!(
+            NymVersionUtil::parse_release_tag("nym-binaries-v1.1.32", &tag_regex).unwrap(),
+            Version::new(1, 1, 32)
+        );
+        assert_eq!(
+            NymVersionUtil::parse_release_tag("nym-binaries-v2024.13-magura", &tag_regex).unwrap(),
+            version("2024.13-magura")
+        );
+        assert!(NymVersionUtil::parse_release_tag("nym-wallet-v1.2.8", &tag_regex).is_err());
+    }
+}